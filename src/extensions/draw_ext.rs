@@ -1,37 +1,99 @@
+#[cfg(windows)]
 use crate::graphics_subsystem::device_context::ScopedDeviceContext;
+#[cfg(windows)]
 use crate::shared::booleanize;
+#[cfg(windows)]
 use winapi::ctypes::c_int;
-use winapi::shared::minwindef::FLOAT;
+#[cfg(windows)]
 use crate::shared::Error;
+#[cfg(windows)]
 use crate::shared::Result;
 
-pub struct Transform(winapi::um::wingdi::XFORM);
+/// A world-space transformation, layout-compatible with `XFORM`.
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Transform {
+    m11: f32,
+    m12: f32,
+    m21: f32,
+    m22: f32,
+    dx: f32,
+    dy: f32,
+}
 
 impl Transform {
     pub const fn new() -> Self {
         Self::new_with_values(&[1.0, 0.0, 0.0, 1.0, 0.0, 0.0])
     }
-    pub const fn new_with_values(values: &[FLOAT; 6]) -> Self {
-        use winapi::um::wingdi::XFORM;
-        Transform(XFORM {
-            eM11: values[0],
-            eM12: values[1],
-            eM21: values[2],
-            eM22: values[3],
-            eDx: values[4],
-            eDy: values[5],
-        })
+
+    pub const fn new_with_values(values: &[f32; 6]) -> Self {
+        Transform {
+            m11: values[0],
+            m12: values[1],
+            m21: values[2],
+            m22: values[3],
+            dx: values[4],
+            dy: values[5],
+        }
+    }
+
+    pub fn values(&self) -> [f32; 6] {
+        [self.m11, self.m12, self.m21, self.m22, self.dx, self.dy]
     }
 }
 
+impl Default for Transform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(windows)]
+mod conversions {
+    use super::Transform;
+    use std::mem::{align_of, size_of};
+    use winapi::um::wingdi::XFORM;
+
+    const _: () = assert!(size_of::<Transform>() == size_of::<XFORM>());
+    const _: () = assert!(align_of::<Transform>() == align_of::<XFORM>());
+
+    impl From<XFORM> for Transform {
+        fn from(v: XFORM) -> Self {
+            Transform::new_with_values(&[v.eM11, v.eM12, v.eM21, v.eM22, v.eDx, v.eDy])
+        }
+    }
+
+    impl From<Transform> for XFORM {
+        fn from(v: Transform) -> Self {
+            XFORM {
+                eM11: v.m11,
+                eM12: v.m12,
+                eM21: v.m21,
+                eM22: v.m22,
+                eDx: v.dx,
+                eDy: v.dy,
+            }
+        }
+    }
+
+    impl Transform {
+        pub(crate) fn as_raw_ptr(&self) -> *const XFORM {
+            self as *const Transform as *const XFORM
+        }
+    }
+}
+
+#[cfg(windows)]
 #[derive(PartialEq)]
 pub struct GraphicsMode(c_int);
 
+#[cfg(windows)]
 impl GraphicsMode {
     pub const COMPATIBLE: GraphicsMode = GraphicsMode(winapi::um::wingdi::GM_COMPATIBLE as c_int);
     pub const ADVANCED: GraphicsMode = GraphicsMode(winapi::um::wingdi::GM_ADVANCED as c_int);
 }
 
+#[cfg(windows)]
 impl<'a> ScopedDeviceContext<'a> {
     pub fn set_graphics_mode(&mut self, graphics_mode: GraphicsMode) -> Result<&mut Self> {
         use winapi::um::wingdi::SetGraphicsMode;
@@ -72,7 +134,7 @@ impl<'a> ScopedDeviceContext<'a> {
     pub fn set_world_transform(&mut self, transform: &Transform) -> Result<&mut Self> {
         use winapi::um::wingdi::SetWorldTransform;
         unsafe {
-            let v = SetWorldTransform(self.data_ref().raw_handle(), &transform.0 as _);
+            let v = SetWorldTransform(self.data_ref().raw_handle(), transform.as_raw_ptr());
             if !booleanize(v) {
                 return Error::last();
             }
@@ -90,7 +152,7 @@ impl<'a> ScopedDeviceContext<'a> {
         unsafe {
             let v = ModifyWorldTransform(
                 self.data_ref().raw_handle(),
-                &transform.0 as _,
+                transform.as_raw_ptr(),
                 MWT_LEFTMULTIPLY as _,
             );
             if !booleanize(v) {
@@ -110,7 +172,7 @@ impl<'a> ScopedDeviceContext<'a> {
         unsafe {
             let v = ModifyWorldTransform(
                 self.data_ref().raw_handle(),
-                &transform.0 as _,
+                transform.as_raw_ptr(),
                 MWT_RIGHTMULTIPLY as _,
             );
             if !booleanize(v) {
@@ -120,3 +182,16 @@ impl<'a> ScopedDeviceContext<'a> {
         Ok(self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transforms_default_to_identity() {
+        assert_eq!(Transform::default(), Transform::new());
+        assert_eq!(Transform::new().values(), [1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+        let values = [2.0, 0.5, -0.5, 2.0, 10.0, -4.0];
+        assert_eq!(Transform::new_with_values(&values).values(), values);
+    }
+}
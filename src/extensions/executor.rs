@@ -0,0 +1,482 @@
+//! A single-threaded executor for futures that run on the UI thread.
+//!
+//! `LocalExecutor` only keeps the tasks and the queue of woken ones; how the
+//! owning thread learns that it should call `run_ready` is left to a
+//! `WakeSignal`. On Windows, `UiExecutor` uses a hidden message-only window
+//! as the signal, so tasks are polled from inside the normal message loop,
+//! and `UiExecutor::sleep` completes from a timer set on that window.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Wake, Waker};
+
+/// Tells the executor's thread that tasks are ready. Called from whichever
+/// thread wakes a task, at most once between two `run_ready` calls.
+pub trait WakeSignal: Send + Sync + 'static {
+    fn signal(&self);
+}
+
+struct ReadyQueue {
+    ids: Mutex<VecDeque<usize>>,
+    signalled: AtomicBool,
+    signal: Box<dyn WakeSignal>,
+}
+
+impl ReadyQueue {
+    fn push(&self, id: usize) {
+        self.ids.lock().unwrap().push_back(id);
+        if !self.signalled.swap(true, Ordering::AcqRel) {
+            self.signal.signal();
+        }
+    }
+}
+
+struct TaskWaker {
+    id: usize,
+    queue: Arc<ReadyQueue>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.queue.push(self.id);
+    }
+}
+
+type LocalTask = Pin<Box<dyn Future<Output = ()>>>;
+
+/// Runs `!Send` futures on the thread that owns it.
+pub struct LocalExecutor {
+    tasks: RefCell<HashMap<usize, LocalTask>>,
+    next_id: Cell<usize>,
+    queue: Arc<ReadyQueue>,
+}
+
+impl LocalExecutor {
+    pub fn new<S: WakeSignal>(signal: S) -> Self {
+        LocalExecutor {
+            tasks: RefCell::new(HashMap::new()),
+            next_id: Cell::new(0),
+            queue: Arc::new(ReadyQueue {
+                ids: Mutex::new(VecDeque::new()),
+                signalled: AtomicBool::new(false),
+                signal: Box::new(signal),
+            }),
+        }
+    }
+
+    /// Queues `future` to be polled by the next `run_ready`. Can be called
+    /// from inside a running task.
+    pub fn spawn<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        let id = self.next_id.get();
+        self.next_id.set(id.wrapping_add(1));
+        self.tasks.borrow_mut().insert(id, Box::pin(future));
+        self.queue.push(id);
+    }
+
+    /// Polls every task woken since the last call and returns how many were
+    /// polled. Tasks woken while this runs are left for the next call, which
+    /// is signalled once this returns.
+    pub fn run_ready(&self) -> usize {
+        // Stays signalled while polling, so a task running a modal loop does
+        // not wake the executor's thread into a call it cannot make.
+        let ready = mem::take(&mut *self.queue.ids.lock().unwrap());
+        let mut polled = 0;
+        for id in ready {
+            // Taken out of the map so the task can spawn while being polled.
+            let task = self.tasks.borrow_mut().remove(&id);
+            let mut task = match task {
+                Some(task) => task,
+                None => continue,
+            };
+            let waker = Waker::from(Arc::new(TaskWaker {
+                id,
+                queue: self.queue.clone(),
+            }));
+            polled += 1;
+            if task
+                .as_mut()
+                .poll(&mut Context::from_waker(&waker))
+                .is_pending()
+            {
+                self.tasks.borrow_mut().insert(id, task);
+            }
+        }
+        self.queue.signalled.store(false, Ordering::Release);
+        // Tasks woken before the store above did not signal.
+        if !self.queue.ids.lock().unwrap().is_empty()
+            && !self.queue.signalled.swap(true, Ordering::AcqRel)
+        {
+            self.queue.signal.signal();
+        }
+        polled
+    }
+
+    /// The number of tasks that have not completed yet.
+    pub fn task_count(&self) -> usize {
+        self.tasks.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.task_count() == 0
+    }
+}
+
+#[cfg(windows)]
+mod ui {
+    use super::{LocalExecutor, WakeSignal};
+    use std::cell::{Cell, RefCell};
+    use std::future::Future;
+    use std::num::NonZeroUsize;
+    use std::pin::Pin;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Waker};
+    use std::time::Duration;
+
+    use crate::windows_subsystem::timer::TimerGuard;
+    use crate::windows_subsystem::ui_thread::UiThread;
+    use crate::windows_subsystem::user_message::UserMessage;
+    use crate::windows_subsystem::window::{ForeignWindow, ForeignWindowClass};
+    use crate::windows_subsystem::window::{WindowBuilder, WindowClassBuilder};
+    use crate::windows_subsystem::wm;
+    use crate::Result;
+
+    const EXECUTOR_WINDOW_CLASS: &str = "apiw.executor";
+
+    fn wake_message() -> UserMessage<()> {
+        // Private to the executor's own window class.
+        UserMessage::from_id(wm::WM_USER)
+    }
+
+    fn executor_window_class() -> Result<ForeignWindowClass> {
+        use winapi::shared::winerror::ERROR_CLASS_ALREADY_EXISTS;
+        let window_proc = crate::window_proc!(state: Rc<LocalExecutor>, |mut request, executor| {
+            request.route_user_message(&wake_message(), |_, ()| {
+                executor.run_ready();
+                Ok(())
+            });
+        });
+        match WindowClassBuilder::new(EXECUTOR_WINDOW_CLASS)
+            .window_proc(window_proc)
+            .create_managed()
+        {
+            Err(ref e) if e.code() == ERROR_CLASS_ALREADY_EXISTS => {
+                Ok(ForeignWindowClass::new_from_name(EXECUTOR_WINDOW_CLASS))
+            }
+            r => r,
+        }
+    }
+
+    struct WindowSignal {
+        hwnd: Arc<AtomicUsize>,
+    }
+
+    impl WakeSignal for WindowSignal {
+        fn signal(&self) {
+            use winapi::um::winuser::PostMessageW;
+            // Zero once the executor is gone; the handle may be reused.
+            let hwnd = self.hwnd.load(Ordering::Acquire);
+            if hwnd == 0 {
+                return;
+            }
+            let args = wake_message().encode(());
+            unsafe {
+                PostMessageW(hwnd as _, args.msg, args.wparam, args.lparam);
+            }
+        }
+    }
+
+    /// A `LocalExecutor` polled by the current thread's message loop.
+    /// Dropping it destroys the hidden window and every unfinished task.
+    pub struct UiExecutor {
+        executor: Rc<LocalExecutor>,
+        window: ForeignWindow,
+        hwnd: Arc<AtomicUsize>,
+    }
+
+    impl UiExecutor {
+        pub fn new(ui: &UiThread) -> Result<Self> {
+            let hwnd = Arc::new(AtomicUsize::new(0));
+            let executor = Rc::new(LocalExecutor::new(WindowSignal { hwnd: hwnd.clone() }));
+            let class = executor_window_class()?;
+            let window = WindowBuilder::new(&class)
+                .message_only()
+                .state(Box::new(executor.clone()))
+                .create(ui)?;
+            hwnd.store(window.data_ref().raw_handle() as usize, Ordering::Release);
+            Ok(UiExecutor {
+                executor,
+                window,
+                hwnd,
+            })
+        }
+
+        pub fn spawn<F>(&self, future: F)
+        where
+            F: Future<Output = ()> + 'static,
+        {
+            self.executor.spawn(future)
+        }
+
+        pub fn executor(&self) -> &LocalExecutor {
+            &self.executor
+        }
+
+        /// A future that completes once `duration` has elapsed, using a
+        /// timer on the executor's window. The timer is only set on the
+        /// first poll.
+        pub fn sleep(&self, duration: Duration) -> Sleep {
+            Sleep {
+                window: self.window.clone(),
+                duration,
+                state: Rc::default(),
+                timer: None,
+            }
+        }
+    }
+
+    impl Drop for UiExecutor {
+        fn drop(&mut self) {
+            self.hwnd.store(0, Ordering::Release);
+            let _ = self.window.destroy();
+        }
+    }
+
+    thread_local! {
+        static NEXT_SLEEP_ID: Cell<usize> = const { Cell::new(1) };
+    }
+
+    #[derive(Default)]
+    struct SleepState {
+        fired: bool,
+        waker: Option<Waker>,
+    }
+
+    /// Returned by `UiExecutor::sleep`.
+    pub struct Sleep {
+        window: ForeignWindow,
+        duration: Duration,
+        state: Rc<RefCell<SleepState>>,
+        timer: Option<TimerGuard>,
+    }
+
+    impl Sleep {
+        fn start(&self) -> Result<TimerGuard> {
+            let id = NEXT_SLEEP_ID.with(|next| {
+                let id = next.get();
+                next.set(id.checked_add(1).unwrap_or(1));
+                NonZeroUsize::new(id).unwrap()
+            });
+            let state = self.state.clone();
+            self.window.set_timer(id, self.duration, move |request| {
+                if let Some(window) = request.window() {
+                    let _ = window.kill_timer(id);
+                }
+                let waker = {
+                    let mut state = state.borrow_mut();
+                    state.fired = true;
+                    state.waker.take()
+                };
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            })
+        }
+    }
+
+    impl Future for Sleep {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+            if self.timer.is_none() {
+                match self.start() {
+                    Ok(timer) => self.timer = Some(timer),
+                    // Without a timer there is nothing to wait for.
+                    Err(_) => return Poll::Ready(()),
+                }
+            }
+            let mut state = self.state.borrow_mut();
+            if state.fired {
+                Poll::Ready(())
+            } else {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+pub use self::ui::{Sleep, UiExecutor};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use std::sync::atomic::AtomicUsize;
+    use std::task::Poll;
+
+    #[derive(Clone, Default)]
+    struct CountingSignal(Arc<AtomicUsize>);
+
+    impl CountingSignal {
+        fn count(&self) -> usize {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    impl WakeSignal for CountingSignal {
+        fn signal(&self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Completes once `value` is set, like a reply arriving later on the
+    /// message queue.
+    #[derive(Clone, Default)]
+    struct Reply(Rc<RefCell<(Option<i32>, Option<Waker>)>>);
+
+    impl Reply {
+        fn complete(&self, value: i32) {
+            let waker = {
+                let mut inner = self.0.borrow_mut();
+                inner.0 = Some(value);
+                inner.1.take()
+            };
+            waker.unwrap().wake();
+        }
+    }
+
+    impl Future for Reply {
+        type Output = i32;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<i32> {
+            let mut inner = self.0.borrow_mut();
+            match inner.0 {
+                Some(value) => Poll::Ready(value),
+                None => {
+                    inner.1 = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn spawned_tasks_share_one_signal() {
+        let signal = CountingSignal::default();
+        let executor = LocalExecutor::new(signal.clone());
+        let log = Rc::new(RefCell::new(Vec::new()));
+        for i in 0..3 {
+            let log = log.clone();
+            executor.spawn(async move { log.borrow_mut().push(i) });
+        }
+        assert_eq!(signal.count(), 1);
+        assert_eq!(executor.run_ready(), 3);
+        assert_eq!(*log.borrow(), vec![0, 1, 2]);
+        assert!(executor.is_empty());
+        assert_eq!(executor.run_ready(), 0);
+    }
+
+    #[test]
+    fn pending_task_resumes_after_wake() {
+        let signal = CountingSignal::default();
+        let executor = LocalExecutor::new(signal.clone());
+        let reply = Reply::default();
+        let result = Rc::new(Cell::new(0));
+        {
+            let reply = reply.clone();
+            let result = result.clone();
+            executor.spawn(async move { result.set(reply.await * 2) });
+        }
+        assert_eq!(executor.run_ready(), 1);
+        assert_eq!(executor.task_count(), 1);
+
+        reply.complete(21);
+        assert_eq!(signal.count(), 2);
+        assert_eq!(executor.run_ready(), 1);
+        assert_eq!(result.get(), 42);
+        assert!(executor.is_empty());
+    }
+
+    #[test]
+    fn tasks_spawned_while_running_wait_for_next_round() {
+        let signal = CountingSignal::default();
+        let executor = Rc::new(LocalExecutor::new(signal.clone()));
+        let ran = Rc::new(Cell::new(false));
+        {
+            let inner = executor.clone();
+            let ran = ran.clone();
+            executor.spawn(async move {
+                inner.spawn(async move { ran.set(true) });
+            });
+        }
+        assert_eq!(executor.run_ready(), 1);
+        assert!(!ran.get());
+        assert_eq!(signal.count(), 2);
+        assert_eq!(executor.run_ready(), 1);
+        assert!(ran.get());
+    }
+
+    #[test]
+    fn wakes_while_running_signal_once_it_returns() {
+        let signal = CountingSignal::default();
+        let executor = LocalExecutor::new(signal.clone());
+        let signals_while_polled = Rc::new(Cell::new(None));
+        {
+            let signal = signal.clone();
+            let signals_while_polled = signals_while_polled.clone();
+            let mut polled = false;
+            executor.spawn(std::future::poll_fn(move |cx| {
+                if polled {
+                    return Poll::Ready(());
+                }
+                polled = true;
+                cx.waker().wake_by_ref();
+                signals_while_polled.set(Some(signal.count()));
+                Poll::Pending
+            }));
+        }
+        assert_eq!(signal.count(), 1);
+        assert_eq!(executor.run_ready(), 1);
+        assert_eq!(signals_while_polled.get(), Some(1));
+        assert_eq!(signal.count(), 2);
+        assert_eq!(executor.run_ready(), 1);
+        assert_eq!(signal.count(), 2);
+        assert!(executor.is_empty());
+    }
+
+    #[test]
+    fn wakers_can_be_used_from_other_threads() {
+        let signal = CountingSignal::default();
+        let executor = LocalExecutor::new(signal.clone());
+        let (sender, receiver) = std::sync::mpsc::channel::<Waker>();
+        let mut sender = Some(sender);
+        executor.spawn(std::future::poll_fn(move |cx| match sender.take() {
+            Some(sender) => {
+                sender.send(cx.waker().clone()).unwrap();
+                Poll::Pending
+            }
+            None => Poll::Ready(()),
+        }));
+        executor.run_ready();
+        let waker = receiver.recv().unwrap();
+        std::thread::spawn(move || waker.wake()).join().unwrap();
+        assert_eq!(signal.count(), 2);
+        assert_eq!(executor.run_ready(), 1);
+        assert!(executor.is_empty());
+    }
+}
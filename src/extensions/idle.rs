@@ -0,0 +1,283 @@
+use crate::Result;
+
+/// A message popped from a `MessagePump`.
+pub enum Pumped<M> {
+    Quit(i32),
+    Message(M),
+}
+
+/// The message queue operations `IdleScheduler::run` needs.
+pub trait MessagePump {
+    type Message;
+
+    /// Whether a message is waiting, without removing it.
+    fn has_pending(&mut self) -> bool;
+
+    /// Blocks until a new message arrives.
+    fn wait(&mut self) -> Result<()>;
+
+    /// Removes the next message, blocking if there is none.
+    fn next(&mut self) -> Result<Pumped<Self::Message>>;
+
+    fn dispatch(&mut self, msg: Self::Message);
+
+    /// Whether handling `msg` should give idle callbacks another turn.
+    /// Frequent housekeeping messages such as WM_PAINT and WM_TIMER
+    /// usually should not.
+    fn restarts_idle(&self, msg: &Self::Message) -> bool {
+        let _ = msg;
+        true
+    }
+}
+
+struct IdleCallback {
+    callback: Box<dyn FnMut(u32) -> bool>,
+    wants_more: bool,
+}
+
+/// Calls registered callbacks while the message queue is empty.
+///
+/// Each callback receives the number of idle rounds since the last message
+/// and returns whether it wants more idle time. Once it returns `false` it
+/// is not called again until a message restarts idle processing.
+pub struct IdleScheduler {
+    callbacks: Vec<IdleCallback>,
+}
+
+impl IdleScheduler {
+    pub fn new() -> Self {
+        IdleScheduler {
+            callbacks: Vec::new(),
+        }
+    }
+
+    pub fn add<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: FnMut(u32) -> bool + 'static,
+    {
+        self.callbacks.push(IdleCallback {
+            callback: Box::new(callback),
+            wants_more: true,
+        });
+        self
+    }
+
+    /// Runs one idle round. Returns whether any callback wants more.
+    pub fn run_idle(&mut self, count: u32) -> bool {
+        let mut any = false;
+        for entry in self.callbacks.iter_mut().filter(|e| e.wants_more) {
+            entry.wants_more = (entry.callback)(count);
+            any |= entry.wants_more;
+        }
+        any
+    }
+
+    pub fn restart(&mut self) {
+        for entry in self.callbacks.iter_mut() {
+            entry.wants_more = true;
+        }
+    }
+
+    /// Pumps messages until quit, running idle rounds whenever the queue
+    /// is empty. Returns the exit code.
+    pub fn run<P: MessagePump>(&mut self, pump: &mut P) -> Result<i32> {
+        let mut idle = true;
+        let mut count = 0;
+        loop {
+            while idle && !pump.has_pending() {
+                idle = self.run_idle(count);
+                count = count.wrapping_add(1);
+            }
+            if !pump.has_pending() {
+                pump.wait()?;
+            }
+            loop {
+                match pump.next()? {
+                    Pumped::Quit(exit_code) => return Ok(exit_code),
+                    Pumped::Message(msg) => {
+                        if pump.restarts_idle(&msg) {
+                            self.restart();
+                            idle = true;
+                            count = 0;
+                        }
+                        pump.dispatch(msg);
+                    }
+                }
+                if !pump.has_pending() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl Default for IdleScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(windows)]
+mod foreign {
+    use super::{IdleScheduler, MessagePump, Pumped};
+    use crate::windows_subsystem::message::QuitOrNormalMsg;
+    use crate::windows_subsystem::message::{ForeignMessageLoop, Message, MessageFilter};
+    use crate::windows_subsystem::wm;
+    use crate::Result;
+
+    impl MessagePump for ForeignMessageLoop {
+        type Message = Message;
+
+        fn has_pending(&mut self) -> bool {
+            self.peek_with_filter_no_yield(&MessageFilter::new())
+                .is_some()
+        }
+
+        fn wait(&mut self) -> Result<()> {
+            self.wait_for_next_incoming()
+        }
+
+        fn next(&mut self) -> Result<Pumped<Message>> {
+            Ok(match self.poll_wait()? {
+                QuitOrNormalMsg::QuitMsg(exit_code) => Pumped::Quit(exit_code),
+                QuitOrNormalMsg::NormalMsg(msg) => Pumped::Message(msg),
+            })
+        }
+
+        fn dispatch(&mut self, msg: Message) {
+            msg.translate();
+            msg.dispatch();
+        }
+
+        fn restarts_idle(&self, msg: &Message) -> bool {
+            !matches!(msg.args().msg, wm::WM_PAINT | wm::WM_TIMER)
+        }
+    }
+
+    impl ForeignMessageLoop {
+        /// Like `run_until_quit`, running `scheduler` whenever the queue is
+        /// empty.
+        pub fn run_with_idle(&mut self, scheduler: &mut IdleScheduler) -> Result<i32> {
+            scheduler.run(self)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Idle(&'static str, u32),
+        Dispatch(u32),
+        Wait,
+    }
+
+    type Log = Rc<RefCell<Vec<Event>>>;
+
+    /// Messages are delivered in batches; `wait` moves to the next batch.
+    struct FakePump {
+        queue: VecDeque<Pumped<u32>>,
+        batches: VecDeque<Vec<Pumped<u32>>>,
+        log: Log,
+    }
+
+    impl MessagePump for FakePump {
+        type Message = u32;
+
+        fn has_pending(&mut self) -> bool {
+            !self.queue.is_empty()
+        }
+
+        fn wait(&mut self) -> Result<()> {
+            self.log.borrow_mut().push(Event::Wait);
+            let batch = self.batches.pop_front().expect("waited with no more input");
+            self.queue.extend(batch);
+            Ok(())
+        }
+
+        fn next(&mut self) -> Result<Pumped<u32>> {
+            Ok(self.queue.pop_front().expect("next on empty queue"))
+        }
+
+        fn dispatch(&mut self, msg: u32) {
+            self.log.borrow_mut().push(Event::Dispatch(msg));
+        }
+
+        fn restarts_idle(&self, msg: &u32) -> bool {
+            *msg != 0
+        }
+    }
+
+    fn pump(batches: Vec<Vec<Pumped<u32>>>, log: &Log) -> FakePump {
+        FakePump {
+            queue: VecDeque::new(),
+            batches: batches.into(),
+            log: log.clone(),
+        }
+    }
+
+    fn counting(name: &'static str, rounds: u32, log: &Log) -> impl FnMut(u32) -> bool {
+        let log = log.clone();
+        move |count| {
+            log.borrow_mut().push(Event::Idle(name, count));
+            count + 1 < rounds
+        }
+    }
+
+    #[test]
+    fn runs_idle_until_callbacks_are_done() {
+        let log = Log::default();
+        let mut scheduler = IdleScheduler::new();
+        scheduler
+            .add(counting("a", 2, &log))
+            .add(counting("b", 1, &log));
+        let mut pump = pump(vec![vec![Pumped::Message(7)], vec![Pumped::Quit(3)]], &log);
+
+        assert_eq!(scheduler.run(&mut pump).unwrap(), 3);
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                Event::Idle("a", 0),
+                Event::Idle("b", 0),
+                Event::Idle("a", 1),
+                Event::Wait,
+                Event::Dispatch(7),
+                Event::Idle("a", 0),
+                Event::Idle("b", 0),
+                Event::Idle("a", 1),
+                Event::Wait,
+            ]
+        );
+    }
+
+    #[test]
+    fn housekeeping_messages_do_not_restart_idle() {
+        let log = Log::default();
+        let mut scheduler = IdleScheduler::new();
+        scheduler.add(counting("a", 1, &log));
+        let mut pump = pump(
+            vec![
+                vec![Pumped::Message(0), Pumped::Message(0)],
+                vec![Pumped::Quit(0)],
+            ],
+            &log,
+        );
+
+        assert_eq!(scheduler.run(&mut pump).unwrap(), 0);
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                Event::Idle("a", 0),
+                Event::Wait,
+                Event::Dispatch(0),
+                Event::Dispatch(0),
+                Event::Wait,
+            ]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Spy++-style tracing of window procedure messages.
+//!
+//! Wrap the closure given to `window_proc!` in `traced` (or
+//! `traced_with_state`) and every message it sees is logged at trace level
+//! under the "apiw" target, together with the response it produced.
+
+use crate::windows_subsystem::window_message::{WindowMessage, WindowProcRequestArgs};
+use crate::windows_subsystem::wm::MessageName;
+
+/// Formats the target window, message name and decoded parameters.
+pub fn format_message(hwnd: usize, args: &WindowProcRequestArgs) -> String {
+    let name = MessageName(args.msg);
+    match args.decode() {
+        WindowMessage::Other(_) => format!(
+            "{:#x} {} wparam={:#x} lparam={:#x}",
+            hwnd, name, args.wparam, args.lparam
+        ),
+        decoded => format!("{:#x} {} {:?}", hwnd, name, decoded),
+    }
+}
+
+/// Formats a window procedure outcome; `None` stands for a message left to
+/// the default procedure.
+pub fn format_result(result: Option<isize>) -> String {
+    match result {
+        Some(r) => format!("Done({})", r),
+        None => "Fallback".to_owned(),
+    }
+}
+
+pub fn format_trace(hwnd: usize, args: &WindowProcRequestArgs, result: Option<isize>) -> String {
+    format!(
+        "{} -> {}",
+        format_message(hwnd, args),
+        format_result(result)
+    )
+}
+
+#[cfg(windows)]
+mod foreign {
+    use super::format_trace;
+    use log::{log_enabled, trace, Level};
+
+    use crate::windows_subsystem::window::{WindowProcRequest, WindowProcResponse};
+
+    fn run_traced<F>(mut request: WindowProcRequest, nest_proc: F)
+    where
+        F: FnOnce(WindowProcRequest),
+    {
+        if !log_enabled!(target: "apiw", Level::Trace) {
+            return nest_proc(request);
+        }
+        let outer = request.response.take();
+        let mut response = WindowProcResponse::Fallback;
+        let (hwnd, args) = (request.hwnd, request.args);
+        nest_proc(WindowProcRequest {
+            hwnd,
+            args,
+            response: outer.as_ref().map(|_| &mut response),
+        });
+        let result = match response {
+            WindowProcResponse::Done(r) => Some(r),
+            WindowProcResponse::Fallback => None,
+        };
+        trace!(target: "apiw", "{}", format_trace(hwnd as usize, &args, result));
+        if let Some(outer) = outer {
+            *outer = response;
+        }
+    }
+
+    /// Logs every message `nest_proc` handles, for use inside `window_proc!`.
+    pub fn traced<F>(nest_proc: F) -> impl for<'a> FnOnce(WindowProcRequest<'a>)
+    where
+        F: for<'a> FnOnce(WindowProcRequest<'a>),
+    {
+        move |request| run_traced(request, nest_proc)
+    }
+
+    /// Like `traced`, for `window_proc!(state: S, ...)`.
+    pub fn traced_with_state<S, F>(
+        nest_proc: F,
+    ) -> impl for<'a, 's> FnOnce(WindowProcRequest<'a>, &'s mut S)
+    where
+        F: for<'a, 's> FnOnce(WindowProcRequest<'a>, &'s mut S),
+    {
+        move |request, state| run_traced(request, |request| nest_proc(request, state))
+    }
+}
+
+#[cfg(windows)]
+pub use self::foreign::{traced, traced_with_state};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::windows_subsystem::wm;
+
+    fn args(msg: u32, wparam: usize, lparam: isize) -> WindowProcRequestArgs {
+        WindowProcRequestArgs {
+            msg,
+            wparam,
+            lparam,
+        }
+    }
+
+    #[test]
+    fn formats_decoded_messages() {
+        assert_eq!(
+            format_message(0x1234, &args(wm::WM_TIMER, 7, 0)),
+            "0x1234 WM_TIMER Timer { id: 7, timer_proc: 0 }"
+        );
+        assert_eq!(
+            format_trace(0x10, &args(wm::WM_CLOSE, 0, 0), Some(0)),
+            "0x10 WM_CLOSE Close -> Done(0)"
+        );
+    }
+
+    #[test]
+    fn formats_undecoded_messages() {
+        assert_eq!(
+            format_trace(0x10, &args(wm::WM_USER + 2, 1, 0x20), None),
+            "0x10 WM_USER+2 wparam=0x1 lparam=0x20 -> Fallback"
+        );
+    }
+}
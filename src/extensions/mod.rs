@@ -1 +1,7 @@
 pub mod draw_ext;
+pub mod executor;
+pub mod idle;
+pub mod message_trace;
+pub mod recording;
+pub mod simulation;
+pub mod ui_channel;
@@ -0,0 +1,440 @@
+//! Recording message streams and replaying them later.
+//!
+//! A recording is saved as plain text, one message per line after a
+//! version header:
+//!
+//! ```text
+//! apiw-recording 1
+//! <elapsed ms> <hwnd> <msg> <wparam> <lparam>
+//! ```
+//!
+//! The elapsed time is decimal milliseconds since recording started. The
+//! other fields are hexadecimal, with `wparam` and `lparam` written as
+//! 64-bit two's complement, so a log reads the same on any host. Lines
+//! that are empty or start with `#` are skipped.
+
+use std::convert::{Infallible, TryFrom};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::windows_subsystem::window_message::WindowProcRequestArgs;
+
+const HEADER: &str = "apiw-recording 1";
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct RecordedMessage {
+    /// Time since the recording started, in whole milliseconds.
+    pub elapsed: Duration,
+    pub hwnd: usize,
+    pub args: WindowProcRequestArgs,
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Recording {
+    pub messages: Vec<RecordedMessage>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ReplayPace {
+    /// Waits between messages as long as the original stream did.
+    RealTime,
+    AsFastAsPossible,
+}
+
+impl Recording {
+    /// Calls `f` for each message in order. With `ReplayPace::RealTime`,
+    /// a message too far in the future to wait for is replayed at once.
+    pub fn replay<F>(&self, pace: ReplayPace, mut f: F)
+    where
+        F: FnMut(&RecordedMessage),
+    {
+        let _ = self.try_replay(pace, |message| -> Result<(), Infallible> {
+            f(message);
+            Ok(())
+        });
+    }
+
+    /// Like `replay`, stopping at the first error `f` returns.
+    pub fn try_replay<E, F>(&self, pace: ReplayPace, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(&RecordedMessage) -> Result<(), E>,
+    {
+        let start = Instant::now();
+        for message in &self.messages {
+            if pace == ReplayPace::RealTime {
+                if let Some(due) = start.checked_add(message.elapsed) {
+                    let now = Instant::now();
+                    if due > now {
+                        std::thread::sleep(due - now);
+                    }
+                }
+            }
+            f(message)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        for m in &self.messages {
+            writeln!(
+                f,
+                "{} {:x} {:x} {:x} {:x}",
+                m.elapsed.as_millis(),
+                m.hwnd,
+                m.args.msg,
+                m.args.wparam as u64,
+                m.args.lparam as i64 as u64
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ParseRecordingErrorKind {
+    MissingHeader,
+    UnsupportedVersion,
+    WrongFieldCount,
+    InvalidNumber,
+}
+
+/// Why a recording could not be parsed, and on which (1-based) line.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ParseRecordingError {
+    pub line: usize,
+    pub kind: ParseRecordingErrorKind,
+}
+
+impl fmt::Display for ParseRecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let what = match self.kind {
+            ParseRecordingErrorKind::MissingHeader => "missing recording header",
+            ParseRecordingErrorKind::UnsupportedVersion => "unsupported recording version",
+            ParseRecordingErrorKind::WrongFieldCount => "expected 5 fields",
+            ParseRecordingErrorKind::InvalidNumber => "invalid number",
+        };
+        write!(f, "line {}: {}", self.line, what)
+    }
+}
+
+impl std::error::Error for ParseRecordingError {}
+
+/// Converts a 64-bit field to the host's width, failing if it does not fit.
+fn narrow<S, T: TryFrom<S>>(v: S) -> Result<T, ParseRecordingErrorKind> {
+    T::try_from(v).map_err(|_| ParseRecordingErrorKind::InvalidNumber)
+}
+
+fn parse_line(line: &str) -> Result<RecordedMessage, ParseRecordingErrorKind> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() != 5 {
+        return Err(ParseRecordingErrorKind::WrongFieldCount);
+    }
+    let hex =
+        |s: &str| u64::from_str_radix(s, 16).map_err(|_| ParseRecordingErrorKind::InvalidNumber);
+    let elapsed = fields[0]
+        .parse::<u64>()
+        .map_err(|_| ParseRecordingErrorKind::InvalidNumber)?;
+    let msg =
+        u32::from_str_radix(fields[2], 16).map_err(|_| ParseRecordingErrorKind::InvalidNumber)?;
+    Ok(RecordedMessage {
+        elapsed: Duration::from_millis(elapsed),
+        hwnd: narrow(hex(fields[1])?)?,
+        args: WindowProcRequestArgs {
+            msg,
+            wparam: narrow(hex(fields[3])?)?,
+            lparam: narrow(hex(fields[4])? as i64)?,
+        },
+    })
+}
+
+impl FromStr for Recording {
+    type Err = ParseRecordingError;
+
+    fn from_str(s: &str) -> Result<Self, ParseRecordingError> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            Some((line, header)) if header.starts_with("apiw-recording ") => {
+                return Err(ParseRecordingError {
+                    line,
+                    kind: ParseRecordingErrorKind::UnsupportedVersion,
+                })
+            }
+            other => {
+                return Err(ParseRecordingError {
+                    line: other.map_or(1, |(line, _)| line),
+                    kind: ParseRecordingErrorKind::MissingHeader,
+                })
+            }
+        }
+        let mut messages = Vec::new();
+        for (line, text) in lines {
+            messages.push(parse_line(text).map_err(|kind| ParseRecordingError { line, kind })?);
+        }
+        Ok(Recording { messages })
+    }
+}
+
+/// Collects messages with the time they were seen.
+pub struct Recorder {
+    start: Instant,
+    recording: Recording,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Recorder {
+            start: Instant::now(),
+            recording: Recording::default(),
+        }
+    }
+
+    pub fn record(&mut self, hwnd: usize, args: WindowProcRequestArgs) {
+        let elapsed = self.start.elapsed();
+        self.record_at(elapsed, hwnd, args);
+    }
+
+    /// Records with an explicit timestamp, truncated to milliseconds.
+    pub fn record_at(&mut self, elapsed: Duration, hwnd: usize, args: WindowProcRequestArgs) {
+        let elapsed = Duration::from_millis(elapsed.as_millis() as u64);
+        self.recording.messages.push(RecordedMessage {
+            elapsed,
+            hwnd,
+            args,
+        });
+    }
+
+    pub fn finish(self) -> Recording {
+        self.recording
+    }
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(windows)]
+mod foreign {
+    use super::{RecordedMessage, Recorder, Recording, ReplayPace};
+    use log::warn;
+    use std::cell::RefCell;
+
+    use crate::shared::booleanize;
+    use crate::shared::ManagedStrategy;
+    use crate::shared::{Error, Result};
+    use crate::windows_subsystem::message::{Message, MessageResult};
+    use crate::windows_subsystem::window::{AnyWindow, WindowProcRequest};
+    use crate::windows_subsystem::window_message::WindowProcRequestArgs;
+    use crate::windows_subsystem::wm::MessageName;
+
+    thread_local! {
+        static ACTIVE_RECORDER: RefCell<Option<Recorder>> = const { RefCell::new(None) };
+    }
+
+    /// Starts recording this thread's messages seen by `recorded` window
+    /// procedures and `Message::dispatch_recorded`, replacing any recording
+    /// in progress.
+    pub fn start_recording() {
+        ACTIVE_RECORDER.with(|r| *r.borrow_mut() = Some(Recorder::new()));
+    }
+
+    pub fn stop_recording() -> Option<Recording> {
+        ACTIVE_RECORDER.with(|r| r.borrow_mut().take().map(Recorder::finish))
+    }
+
+    fn record(hwnd: usize, args: WindowProcRequestArgs) {
+        ACTIVE_RECORDER.with(|r| {
+            if let Some(recorder) = r.borrow_mut().as_mut() {
+                recorder.record(hwnd, args);
+            }
+        });
+    }
+
+    /// Records every message `nest_proc` receives, for use inside
+    /// `window_proc!`.
+    pub fn recorded<F>(nest_proc: F) -> impl for<'a> FnOnce(WindowProcRequest<'a>)
+    where
+        F: for<'a> FnOnce(WindowProcRequest<'a>),
+    {
+        move |request| {
+            record(request.hwnd as usize, request.args);
+            nest_proc(request)
+        }
+    }
+
+    impl Message {
+        /// Like `dispatch`, recording the message first.
+        pub fn dispatch_recorded(self) -> MessageResult {
+            let hwnd = self
+                .window()
+                .map_or(0, |window| window.data_ref().raw_handle() as usize);
+            record(hwnd, self.args());
+            self.dispatch()
+        }
+    }
+
+    impl Recording {
+        /// Sends every message to `window`, whichever window it was
+        /// recorded for, and returns how many were skipped. Messages whose
+        /// `wparam` or `lparam` may be a pointer are skipped, since what
+        /// they pointed to is long gone. Fails with
+        /// `ERROR_INVALID_WINDOW_HANDLE`, sending no more, once the window
+        /// is destroyed.
+        pub fn replay_to_window<T: ManagedStrategy>(
+            &self,
+            window: &AnyWindow<T>,
+            pace: ReplayPace,
+        ) -> Result<usize> {
+            use winapi::shared::winerror::ERROR_INVALID_WINDOW_HANDLE;
+            use winapi::um::winuser::IsWindow;
+            let mut skipped = 0;
+            self.try_replay(pace, |message: &RecordedMessage| {
+                if !message.args.is_pointer_free() {
+                    warn!(target: "apiw", "Skipped replaying a message that may carry a pointer: {}",
+                          MessageName(message.args.msg));
+                    skipped += 1;
+                    return Ok(());
+                }
+                if !unsafe { booleanize(IsWindow(window.data_ref().raw_handle())) } {
+                    return Err(Error::from_code(ERROR_INVALID_WINDOW_HANDLE));
+                }
+                window.send_plain_message(message.args)?;
+                Ok(())
+            })?;
+            Ok(skipped)
+        }
+    }
+}
+
+#[cfg(windows)]
+pub use self::foreign::{recorded, start_recording, stop_recording};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::windows_subsystem::wm;
+
+    fn sample() -> Recording {
+        let mut recorder = Recorder::new();
+        recorder.record_at(
+            Duration::from_millis(0),
+            0x1_0040,
+            WindowProcRequestArgs {
+                msg: wm::WM_MOUSEMOVE,
+                wparam: 1,
+                lparam: 0x0014_000A,
+            },
+        );
+        recorder.record_at(
+            Duration::from_micros(16_700),
+            0x1_0040,
+            WindowProcRequestArgs {
+                msg: wm::WM_MOUSEWHEEL,
+                wparam: 0xFF88_0000,
+                lparam: -1,
+            },
+        );
+        recorder.finish()
+    }
+
+    #[test]
+    fn writes_stable_text() {
+        assert_eq!(
+            sample().to_string(),
+            "apiw-recording 1\n\
+             0 10040 200 1 14000a\n\
+             16 10040 20a ff880000 ffffffffffffffff\n"
+        );
+    }
+
+    #[test]
+    fn round_trips_recordings() {
+        let recording = sample();
+        let text = format!("# captured on a test host\n\n{}", recording);
+        assert_eq!(text.parse::<Recording>(), Ok(recording));
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        let error = |text: &str| text.parse::<Recording>().unwrap_err();
+        assert_eq!(
+            error("0 1 2 3 4\n"),
+            ParseRecordingError {
+                line: 1,
+                kind: ParseRecordingErrorKind::MissingHeader
+            }
+        );
+        assert_eq!(
+            error("apiw-recording 2\n").kind,
+            ParseRecordingErrorKind::UnsupportedVersion
+        );
+        assert_eq!(
+            error("apiw-recording 1\n0 1 2 3\n"),
+            ParseRecordingError {
+                line: 2,
+                kind: ParseRecordingErrorKind::WrongFieldCount
+            }
+        );
+        assert_eq!(
+            error("apiw-recording 1\n0 1 2 3 zz\n").to_string(),
+            "line 2: invalid number"
+        );
+    }
+
+    #[test]
+    fn rejects_fields_too_wide_for_the_host() {
+        assert_eq!(
+            narrow::<u64, u32>(0x1_0000_0000),
+            Err(ParseRecordingErrorKind::InvalidNumber)
+        );
+        assert_eq!(narrow::<i64, i32>(-1), Ok(-1));
+        let parsed = "apiw-recording 1\n0 1 2 100000000 0\n".parse::<Recording>();
+        if cfg!(target_pointer_width = "64") {
+            assert_eq!(
+                parsed.unwrap().messages[0].args.wparam as u64,
+                0x1_0000_0000
+            );
+        } else {
+            assert_eq!(
+                parsed.unwrap_err().kind,
+                ParseRecordingErrorKind::InvalidNumber
+            );
+        }
+    }
+
+    #[test]
+    fn replays_in_order() {
+        let recording = sample();
+        let mut seen = Vec::new();
+        recording.replay(ReplayPace::AsFastAsPossible, |m| seen.push(m.args.msg));
+        assert_eq!(seen, vec![wm::WM_MOUSEMOVE, wm::WM_MOUSEWHEEL]);
+    }
+
+    #[test]
+    fn replays_unreachable_times_without_waiting() {
+        let recording = Recording {
+            messages: vec![RecordedMessage {
+                elapsed: Duration::MAX,
+                hwnd: 1,
+                args: WindowProcRequestArgs {
+                    msg: wm::WM_NULL,
+                    wparam: 0,
+                    lparam: 0,
+                },
+            }],
+        };
+        let mut seen = 0;
+        recording.replay(ReplayPace::RealTime, |_| seen += 1);
+        assert_eq!(seen, 1);
+    }
+}
@@ -0,0 +1,518 @@
+//! A headless model of a thread's USER32 message queue.
+//!
+//! `SimulatedQueue` hands out messages in the order `GetMessage` does:
+//! messages sent from other threads, then posted messages, then input, then
+//! a synthesized `WM_PAINT` for windows with an invalid region, then
+//! `WM_TIMER` for due timers, and `WM_QUIT` only once nothing else is left.
+//! Time only moves when `advance` is called, so tests are deterministic.
+//! Windows are plain identifiers, which the `route_*` methods of a
+//! `SimWindowProcRequest` hand to their closures.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::extensions::idle::Pumped;
+use crate::windows_subsystem::timer::timer_interval_millis;
+use crate::windows_subsystem::window_message::{MessageTime, WindowMessage, WindowProcRequestArgs};
+use crate::windows_subsystem::window_route::{
+    AnyWindowProcRequest, RouteTarget, WindowProcResponse,
+};
+use crate::windows_subsystem::wm;
+
+/// A message taken from the queue.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SimMessage {
+    pub hwnd: usize,
+    pub args: WindowProcRequestArgs,
+    pub time: MessageTime,
+}
+
+/// The window procedure for simulated windows. Returning `None` leaves the
+/// message to the default processing, which for `WM_PAINT` validates the
+/// window.
+pub trait SimWindowProc {
+    fn handle(
+        &mut self,
+        queue: &mut SimulatedQueue,
+        hwnd: usize,
+        args: WindowProcRequestArgs,
+    ) -> Option<isize>;
+}
+
+impl<F> SimWindowProc for F
+where
+    F: FnMut(&mut SimulatedQueue, usize, WindowProcRequestArgs) -> Option<isize>,
+{
+    fn handle(
+        &mut self,
+        queue: &mut SimulatedQueue,
+        hwnd: usize,
+        args: WindowProcRequestArgs,
+    ) -> Option<isize> {
+        self(queue, hwnd, args)
+    }
+}
+
+struct SimTimer {
+    hwnd: usize,
+    id: usize,
+    interval: u32,
+    due: u32,
+}
+
+#[derive(Default)]
+pub struct SimulatedQueue {
+    now: u32,
+    sent: VecDeque<(usize, WindowProcRequestArgs)>,
+    posted: VecDeque<(usize, WindowProcRequestArgs)>,
+    input: VecDeque<(usize, WindowProcRequestArgs)>,
+    invalid: Vec<usize>,
+    timers: Vec<SimTimer>,
+    quit: Option<i32>,
+}
+
+impl SimulatedQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn now(&self) -> MessageTime {
+        MessageTime::from_ticks(self.now)
+    }
+
+    /// Moves the clock forward, making timers due.
+    pub fn advance(&mut self, duration: Duration) {
+        let millis = duration.as_millis().min(u128::from(u32::MAX)) as u32;
+        self.now = self.now.wrapping_add(millis);
+    }
+
+    /// Queues a message sent from another thread.
+    pub fn send(&mut self, hwnd: usize, args: WindowProcRequestArgs) {
+        self.sent.push_back((hwnd, args));
+    }
+
+    pub fn post(&mut self, hwnd: usize, args: WindowProcRequestArgs) {
+        self.posted.push_back((hwnd, args));
+    }
+
+    /// Queues keyboard or mouse input, which comes after posted messages.
+    pub fn post_input(&mut self, hwnd: usize, args: WindowProcRequestArgs) {
+        self.input.push_back((hwnd, args));
+    }
+
+    pub fn post_quit(&mut self, exit_code: i32) {
+        self.quit = Some(exit_code);
+    }
+
+    pub fn invalidate(&mut self, hwnd: usize) {
+        if !self.invalid.contains(&hwnd) {
+            self.invalid.push(hwnd);
+        }
+    }
+
+    pub fn validate(&mut self, hwnd: usize) {
+        self.invalid.retain(|&h| h != hwnd);
+    }
+
+    pub fn is_invalid(&self, hwnd: usize) -> bool {
+        self.invalid.contains(&hwnd)
+    }
+
+    /// Sets or resets a timer, first due one `interval` from now.
+    pub fn set_timer(&mut self, hwnd: usize, id: usize, interval: Duration) {
+        self.kill_timer(hwnd, id);
+        let interval = timer_interval_millis(interval);
+        self.timers.push(SimTimer {
+            hwnd,
+            id,
+            interval,
+            due: self.now.wrapping_add(interval),
+        });
+    }
+
+    pub fn kill_timer(&mut self, hwnd: usize, id: usize) -> bool {
+        let before = self.timers.len();
+        self.timers.retain(|t| !(t.hwnd == hwnd && t.id == id));
+        self.timers.len() != before
+    }
+
+    fn take_due_timer(&mut self) -> Option<(usize, WindowProcRequestArgs)> {
+        let now = self.now;
+        let timer = self
+            .timers
+            .iter_mut()
+            .filter(|t| (now.wrapping_sub(t.due) as i32) >= 0)
+            .min_by_key(|t| t.due.wrapping_sub(now) as i32)?;
+        // Missed periods collapse into a single WM_TIMER.
+        timer.due = now.wrapping_add(timer.interval);
+        Some((
+            timer.hwnd,
+            WindowProcRequestArgs {
+                msg: wm::WM_TIMER,
+                wparam: timer.id,
+                lparam: 0,
+            },
+        ))
+    }
+
+    /// Removes the next message by the queue's priority rules, or returns
+    /// `None` if nothing is waiting.
+    pub fn next_message(&mut self) -> Option<Pumped<SimMessage>> {
+        let next = self
+            .sent
+            .pop_front()
+            .or_else(|| self.posted.pop_front())
+            .or_else(|| self.input.pop_front())
+            .or_else(|| {
+                self.invalid.first().map(|&hwnd| {
+                    (
+                        hwnd,
+                        WindowProcRequestArgs {
+                            msg: wm::WM_PAINT,
+                            wparam: 0,
+                            lparam: 0,
+                        },
+                    )
+                })
+            })
+            .or_else(|| self.take_due_timer());
+        match next {
+            Some((hwnd, args)) => Some(Pumped::Message(SimMessage {
+                hwnd,
+                args,
+                time: self.now(),
+            })),
+            None => self.quit.take().map(Pumped::Quit),
+        }
+    }
+
+    /// Passes `msg` to `window_proc`, applying default processing when it
+    /// returns `None`.
+    pub fn dispatch<P: SimWindowProc>(
+        &mut self,
+        msg: &SimMessage,
+        window_proc: &mut P,
+    ) -> Option<isize> {
+        let result = window_proc.handle(self, msg.hwnd, msg.args);
+        if result.is_none() && msg.args.msg == wm::WM_PAINT {
+            self.validate(msg.hwnd);
+        }
+        result
+    }
+
+    /// Dispatches messages until `WM_QUIT` or until the queue is empty.
+    /// Returns the exit code if `WM_QUIT` was reached.
+    pub fn run<P: SimWindowProc>(&mut self, window_proc: &mut P) -> Option<i32> {
+        loop {
+            match self.next_message()? {
+                Pumped::Quit(exit_code) => return Some(exit_code),
+                Pumped::Message(msg) => {
+                    self.dispatch(&msg, window_proc);
+                }
+            }
+        }
+    }
+}
+
+/// Decodes each message and passes it to `handler`, as the routes of a
+/// window procedure see it, without needing real windows. Returning `true`
+/// answers the message with the result a handled message returns; `false`
+/// leaves it to the default processing.
+pub fn decoded_window_proc<F>(mut handler: F) -> impl SimWindowProc
+where
+    F: FnMut(&mut SimulatedQueue, usize, WindowMessage) -> bool,
+{
+    move |queue: &mut SimulatedQueue, hwnd: usize, args: WindowProcRequestArgs| {
+        if handler(queue, hwnd, args.decode()) {
+            Some(args.handled_result())
+        } else {
+            None
+        }
+    }
+}
+
+impl RouteTarget for usize {
+    type Window = usize;
+
+    fn window(self) -> Option<usize> {
+        Some(self)
+    }
+
+    fn id(self) -> usize {
+        self
+    }
+}
+
+/// A request for a simulated window, routed like a `WindowProcRequest`.
+pub type SimWindowProcRequest<'a> = AnyWindowProcRequest<'a, usize>;
+
+/// Passes each message to `handler` as a request, the way `window_proc!`
+/// passes one to its closure, so the same routes can be tested without
+/// real windows. Messages left unanswered get the default processing.
+pub fn routed_window_proc<F>(mut handler: F) -> impl SimWindowProc
+where
+    F: FnMut(&mut SimulatedQueue, SimWindowProcRequest),
+{
+    move |queue: &mut SimulatedQueue, hwnd: usize, args: WindowProcRequestArgs| {
+        let mut response = WindowProcResponse::Fallback;
+        handler(
+            queue,
+            AnyWindowProcRequest {
+                hwnd,
+                args,
+                response: Some(&mut response),
+            },
+        );
+        match response {
+            WindowProcResponse::Done(r) => Some(r),
+            WindowProcResponse::Fallback => None,
+        }
+    }
+}
+
+/// Drives a `window_proc!` translator from the simulated queue. The window
+/// identifiers must be real window handles for routes to find their window.
+/// The real procedure validates on `WM_PAINT`, so the simulated window is
+/// validated as well.
+#[cfg(windows)]
+pub fn raw_window_proc(
+    window_proc: unsafe extern "system" fn(
+        winapi::shared::windef::HWND,
+        winapi::shared::minwindef::UINT,
+        winapi::shared::minwindef::WPARAM,
+        winapi::shared::minwindef::LPARAM,
+    ) -> winapi::shared::minwindef::LRESULT,
+) -> impl SimWindowProc {
+    move |queue: &mut SimulatedQueue, hwnd: usize, args: WindowProcRequestArgs| {
+        let r = unsafe { window_proc(hwnd as _, args.msg, args.wparam, args.lparam) };
+        if args.msg == wm::WM_PAINT {
+            queue.validate(hwnd);
+        }
+        Some(r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(msg: u32) -> WindowProcRequestArgs {
+        WindowProcRequestArgs {
+            msg,
+            wparam: 0,
+            lparam: 0,
+        }
+    }
+
+    fn drain(queue: &mut SimulatedQueue) -> Vec<(usize, u32)> {
+        let mut seen = Vec::new();
+        let mut record = |_: &mut SimulatedQueue, hwnd: usize, args: WindowProcRequestArgs| {
+            seen.push((hwnd, args.msg));
+            None
+        };
+        queue.run(&mut record);
+        seen
+    }
+
+    #[test]
+    fn follows_queue_priorities() {
+        let mut queue = SimulatedQueue::new();
+        queue.set_timer(1, 5, Duration::from_millis(100));
+        queue.post_quit(0);
+        queue.invalidate(2);
+        queue.post_input(1, args(wm::WM_KEYDOWN));
+        queue.post(1, args(wm::WM_USER));
+        queue.send(2, args(wm::WM_SETTEXT));
+        queue.advance(Duration::from_millis(100));
+
+        assert_eq!(
+            drain(&mut queue),
+            vec![
+                (2, wm::WM_SETTEXT),
+                (1, wm::WM_USER),
+                (1, wm::WM_KEYDOWN),
+                (2, wm::WM_PAINT),
+                (1, wm::WM_TIMER),
+            ]
+        );
+        assert!(queue.next_message().is_none());
+    }
+
+    #[test]
+    fn paint_repeats_until_validated() {
+        let mut queue = SimulatedQueue::new();
+        queue.invalidate(1);
+        let mut paints = 0;
+        let mut window_proc =
+            |queue: &mut SimulatedQueue, hwnd: usize, args: WindowProcRequestArgs| {
+                assert_eq!(args.msg, wm::WM_PAINT);
+                paints += 1;
+                if paints == 3 {
+                    queue.validate(hwnd);
+                    queue.post_quit(4);
+                }
+                Some(0)
+            };
+        assert_eq!(queue.run(&mut window_proc), Some(4));
+        assert_eq!(paints, 3);
+        assert!(!queue.is_invalid(1));
+    }
+
+    #[test]
+    fn timers_coalesce_and_reschedule() {
+        let mut queue = SimulatedQueue::new();
+        queue.set_timer(1, 9, Duration::from_millis(50));
+        assert!(queue.next_message().is_none());
+
+        queue.advance(Duration::from_millis(175));
+        assert_eq!(drain(&mut queue), vec![(1, wm::WM_TIMER)]);
+        queue.advance(Duration::from_millis(49));
+        assert!(queue.next_message().is_none());
+        queue.advance(Duration::from_millis(1));
+        assert_eq!(drain(&mut queue), vec![(1, wm::WM_TIMER)]);
+
+        assert!(queue.kill_timer(1, 9));
+        queue.advance(Duration::from_secs(1));
+        assert!(queue.next_message().is_none());
+    }
+
+    #[test]
+    fn messages_posted_while_handling_are_delivered() {
+        let mut queue = SimulatedQueue::new();
+        queue.post(1, args(wm::WM_CREATE));
+        let mut log = Vec::new();
+        let mut window_proc =
+            |queue: &mut SimulatedQueue, _: usize, args: WindowProcRequestArgs| {
+                log.push(args.msg);
+                match args.msg {
+                    wm::WM_CREATE => queue.post(1, self::args(wm::WM_CLOSE)),
+                    wm::WM_CLOSE => queue.post_quit(2),
+                    _ => {}
+                }
+                Some(0)
+            };
+        assert_eq!(queue.run(&mut window_proc), Some(2));
+        assert_eq!(log, vec![wm::WM_CREATE, wm::WM_CLOSE]);
+    }
+
+    #[test]
+    fn routes_decoded_messages() {
+        use crate::graphics_subsystem::Size;
+        use crate::windows_subsystem::window_message::SizeKind;
+
+        let mut queue = SimulatedQueue::new();
+        queue.invalidate(1);
+        queue.post(
+            1,
+            WindowProcRequestArgs {
+                msg: wm::WM_SIZE,
+                wparam: 2,
+                lparam: (480 << 16) | 640,
+            },
+        );
+        queue.post_input(
+            1,
+            WindowProcRequestArgs {
+                msg: wm::WM_XBUTTONUP,
+                wparam: 0x0001_0000,
+                lparam: 0,
+            },
+        );
+        let mut sizes = Vec::new();
+        let mut window_proc =
+            decoded_window_proc(|_: &mut SimulatedQueue, _: usize, message| match message {
+                WindowMessage::Size { kind, size } => {
+                    sizes.push((kind, size));
+                    true
+                }
+                WindowMessage::MouseButtonUp { .. } => true,
+                _ => false,
+            });
+
+        let mut results = Vec::new();
+        while let Some(Pumped::Message(msg)) = queue.next_message() {
+            results.push((msg.args.msg, queue.dispatch(&msg, &mut window_proc)));
+        }
+        drop(window_proc);
+        assert_eq!(
+            results,
+            vec![
+                (wm::WM_SIZE, Some(0)),
+                (wm::WM_XBUTTONUP, Some(1)),
+                (wm::WM_PAINT, None),
+            ]
+        );
+        assert_eq!(sizes, vec![(SizeKind::Maximized, Size::new(640, 480))]);
+        assert!(!queue.is_invalid(1));
+    }
+
+    #[test]
+    fn runs_request_routes() {
+        use crate::windows_subsystem::keyboard::VirtualKey;
+
+        let mut queue = SimulatedQueue::new();
+        queue.post_input(
+            1,
+            WindowProcRequestArgs {
+                msg: wm::WM_KEYDOWN,
+                wparam: 0x41,
+                lparam: 1,
+            },
+        );
+        let mut units = [0u16; 2];
+        '\u{1F600}'.encode_utf16(&mut units);
+        for &unit in units.iter() {
+            queue.post_input(
+                1,
+                WindowProcRequestArgs {
+                    msg: wm::WM_CHAR,
+                    wparam: unit as usize,
+                    lparam: 1,
+                },
+            );
+        }
+        queue.set_timer(2, 7, Duration::from_millis(10));
+        queue.advance(Duration::from_millis(10));
+
+        let mut log = Vec::new();
+        let mut window_proc = routed_window_proc(|queue: &mut SimulatedQueue, mut request| {
+            request
+                .route_key(|&hwnd, key| {
+                    log.push(format!("key {} {:?}", hwnd, key.virtual_key()));
+                    Ok(key.virtual_key() == VirtualKey::from_code(0x41))
+                })
+                .route_char(|&hwnd, c| {
+                    log.push(format!("char {} {}", hwnd, c.character()));
+                    Ok(false)
+                })
+                .route_timer(|&hwnd, id| {
+                    log.push(format!("timer {} {}", hwnd, id));
+                    queue.kill_timer(hwnd, id.get());
+                    Ok(())
+                });
+        });
+
+        let mut results = Vec::new();
+        while let Some(Pumped::Message(msg)) = queue.next_message() {
+            results.push((msg.args.msg, queue.dispatch(&msg, &mut window_proc)));
+        }
+        drop(window_proc);
+        assert_eq!(
+            results,
+            vec![
+                (wm::WM_KEYDOWN, Some(0)),
+                (wm::WM_CHAR, Some(0)),
+                (wm::WM_CHAR, Some(0)),
+                (wm::WM_TIMER, Some(0)),
+            ]
+        );
+        assert_eq!(
+            log,
+            vec![
+                format!("key 1 {:?}", VirtualKey::from_code(0x41)),
+                "char 1 \u{1F600}".to_string(),
+                "timer 2 7".to_string(),
+            ]
+        );
+    }
+}
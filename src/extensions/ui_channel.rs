@@ -0,0 +1,223 @@
+//! A channel from any thread to the UI thread.
+//!
+//! The receiving end is a hidden message-only window on the thread that
+//! created it. Each `UiSender::send` posts it a wake-up message unless one
+//! is already pending, and the window procedure drains the channel into the
+//! handler from inside the normal message loop.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+
+/// Whether a wake-up is pending, shared by the senders and the window.
+struct WakeState {
+    pending: AtomicBool,
+}
+
+impl WakeState {
+    fn new() -> Self {
+        WakeState {
+            pending: AtomicBool::new(false),
+        }
+    }
+
+    /// Called after sending a value; true when the caller has to post a
+    /// wake-up.
+    fn needs_wake(&self) -> bool {
+        !self.pending.swap(true, Ordering::AcqRel)
+    }
+
+    /// Passes every queued value to `handler`. The wake-up stays pending
+    /// until the queue is found empty, so values sent meanwhile, even while
+    /// `handler` runs a modal loop, are drained here instead of relying on
+    /// a wake-up message that loop could not deliver.
+    fn drain<T>(&self, receiver: &mpsc::Receiver<T>, mut handler: impl FnMut(T)) {
+        loop {
+            while let Ok(value) = receiver.try_recv() {
+                handler(value);
+            }
+            self.pending.store(false, Ordering::Release);
+            // A value sent before the store above saw the wake-up pending
+            // and did not post one.
+            match receiver.try_recv() {
+                Ok(value) => {
+                    self.pending.store(true, Ordering::Release);
+                    handler(value);
+                }
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod ui {
+    use super::WakeState;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::{self, SendError};
+    use std::sync::Arc;
+
+    use crate::windows_subsystem::ui_thread::UiThread;
+    use crate::windows_subsystem::user_message::UserMessage;
+    use crate::windows_subsystem::window::{ForeignWindow, ForeignWindowClass};
+    use crate::windows_subsystem::window::{WindowBuilder, WindowClassBuilder};
+    use crate::windows_subsystem::wm;
+    use crate::Result;
+
+    const CHANNEL_WINDOW_CLASS: &str = "apiw.ui_channel";
+
+    type ChannelDrain = Box<dyn FnMut()>;
+
+    fn wake_message() -> UserMessage<()> {
+        // Private to the channel's own window class.
+        UserMessage::from_id(wm::WM_USER)
+    }
+
+    fn channel_window_class() -> Result<ForeignWindowClass> {
+        use winapi::shared::winerror::ERROR_CLASS_ALREADY_EXISTS;
+        let window_proc = crate::window_proc!(state: ChannelDrain, |mut request, drain| {
+            request.route_user_message(&wake_message(), |_, ()| {
+                (drain)();
+                Ok(())
+            });
+        });
+        match WindowClassBuilder::new(CHANNEL_WINDOW_CLASS)
+            .window_proc(window_proc)
+            .create_managed()
+        {
+            Err(ref e) if e.code() == ERROR_CLASS_ALREADY_EXISTS => {
+                Ok(ForeignWindowClass::new_from_name(CHANNEL_WINDOW_CLASS))
+            }
+            r => r,
+        }
+    }
+
+    struct WakeTarget {
+        hwnd: AtomicUsize,
+        state: WakeState,
+    }
+
+    /// The sending end; can be cloned and moved to other threads.
+    pub struct UiSender<T> {
+        sender: mpsc::Sender<T>,
+        target: Arc<WakeTarget>,
+    }
+
+    impl<T> Clone for UiSender<T> {
+        fn clone(&self) -> Self {
+            UiSender {
+                sender: self.sender.clone(),
+                target: self.target.clone(),
+            }
+        }
+    }
+
+    impl<T: Send> UiSender<T> {
+        /// Fails, handing the value back, once the receiver has been dropped.
+        pub fn send(&self, value: T) -> std::result::Result<(), SendError<T>> {
+            use winapi::um::winuser::PostMessageW;
+            self.sender.send(value)?;
+            if self.target.state.needs_wake() {
+                let args = wake_message().encode(());
+                unsafe {
+                    PostMessageW(
+                        self.target.hwnd.load(Ordering::Acquire) as _,
+                        args.msg,
+                        args.wparam,
+                        args.lparam,
+                    );
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// The receiving end. Dropping it destroys the hidden window and closes
+    /// the channel.
+    pub struct UiReceiver {
+        window: ForeignWindow,
+    }
+
+    impl Drop for UiReceiver {
+        fn drop(&mut self) {
+            let _ = self.window.destroy();
+        }
+    }
+
+    /// Creates a channel whose values are passed to `handler` on the `ui`
+    /// thread while its message loop runs.
+    pub fn ui_channel<T, F>(ui: &UiThread, mut handler: F) -> Result<(UiSender<T>, UiReceiver)>
+    where
+        T: Send + 'static,
+        F: FnMut(T) + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let target = Arc::new(WakeTarget {
+            hwnd: AtomicUsize::new(0),
+            state: WakeState::new(),
+        });
+        let drain_target = target.clone();
+        let drain: ChannelDrain =
+            Box::new(move || drain_target.state.drain(&receiver, &mut handler));
+        let class = channel_window_class()?;
+        let window = WindowBuilder::new(&class)
+            .message_only()
+            .state(Box::new(drain))
+            .create(ui)?;
+        target
+            .hwnd
+            .store(window.data_ref().raw_handle() as usize, Ordering::Release);
+        Ok((UiSender { sender, target }, UiReceiver { window }))
+    }
+}
+
+#[cfg(windows)]
+pub use self::ui::{ui_channel, UiReceiver, UiSender};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_wake_until_drained() {
+        let state = WakeState::new();
+        let (sender, receiver) = mpsc::channel();
+        sender.send(1).unwrap();
+        assert!(state.needs_wake());
+        sender.send(2).unwrap();
+        assert!(!state.needs_wake());
+
+        let mut received = Vec::new();
+        state.drain(&receiver, |value| received.push(value));
+        assert_eq!(received, vec![1, 2]);
+
+        sender.send(3).unwrap();
+        assert!(state.needs_wake());
+    }
+
+    #[test]
+    fn values_sent_while_draining_are_drained() {
+        let state = WakeState::new();
+        let (sender, receiver) = mpsc::channel();
+        sender.send(0).unwrap();
+        assert!(state.needs_wake());
+
+        // The handler stands in for a modal loop that cannot deliver a
+        // wake-up to the channel's window.
+        let mut received = Vec::new();
+        let mut posted = 0;
+        state.drain(&receiver, |value| {
+            received.push(value);
+            if value < 3 {
+                sender.send(value + 1).unwrap();
+                if state.needs_wake() {
+                    posted += 1;
+                }
+            }
+        });
+        assert_eq!(received, vec![0, 1, 2, 3]);
+        assert_eq!(posted, 0);
+
+        sender.send(4).unwrap();
+        assert!(state.needs_wake());
+    }
+}
@@ -9,7 +9,9 @@ use crate::shared::ManagedData;
 use crate::shared::ManagedEntity;
 use crate::shared::ManagedStrategy;
 
-use crate::graphics_subsystem::object::{Bitmap, Pen};
+use crate::graphics_subsystem::object::{BitmapInner, PenInner};
+use crate::windows_subsystem::ui_thread::UiThread;
+use std::any::Any;
 use winapi::shared::windef::HBITMAP;
 use winapi::shared::windef::HPEN;
 
@@ -27,9 +29,9 @@ pub struct DeviceContextInner {
     handle: HDC,
     kind: DeviceContextInnerKind,
     tracking_pen_original: Option<HPEN>,
-    tracking_pen_active: Option<Pen>,
+    tracking_pen_active: Option<Box<dyn Any>>,
     tracking_bitmap_original: Option<HBITMAP>,
-    tracking_bitmap_active: Option<Bitmap>,
+    tracking_bitmap_active: Option<Box<dyn Any>>,
 }
 
 impl DeviceContextInner {
@@ -54,8 +56,12 @@ impl DeviceContextInner {
         }
     }
 
-    pub(crate) fn track_active_pen(&mut self, v: Pen) {
-        self.tracking_pen_active = Some(v);
+    /// Keeps the selected pen alive, whatever its strategy.
+    pub(crate) fn track_active_pen<S: ManagedStrategy + 'static>(
+        &mut self,
+        v: ManagedEntity<PenInner, S>,
+    ) {
+        self.tracking_pen_active = Some(Box::new(v));
     }
 
     pub(crate) fn track_old_bitmap(&mut self, v: HBITMAP) {
@@ -64,8 +70,11 @@ impl DeviceContextInner {
         }
     }
 
-    pub(crate) fn track_active_bitmap(&mut self, v: Bitmap) {
-        self.tracking_bitmap_active = Some(v);
+    pub(crate) fn track_active_bitmap<S: ManagedStrategy + 'static>(
+        &mut self,
+        v: ManagedEntity<BitmapInner, S>,
+    ) {
+        self.tracking_bitmap_active = Some(Box::new(v));
     }
 
     pub(crate) fn restore_to_tracked_state(&mut self) {
@@ -127,8 +136,13 @@ impl<'a> ScopedDeviceContext<'a> {
 }
 
 impl LocalDeviceContext {
-    pub fn new_compatible_memory_dc(dc: &ScopedDeviceContext) -> Result<LocalDeviceContext> {
+    /// The device context is used from the thread `ui` was obtained on.
+    pub fn new_compatible_memory_dc(
+        ui: &UiThread,
+        dc: &ScopedDeviceContext,
+    ) -> Result<LocalDeviceContext> {
         use winapi::um::wingdi::CreateCompatibleDC;
+        ui.check()?;
         let memdc = unsafe {
             let h = CreateCompatibleDC(dc.data_ref().raw_handle());
             if h.is_null() {
@@ -142,9 +156,12 @@ impl LocalDeviceContext {
         ))
     }
 
-    pub fn new_compatible_memory_dc_for_current_screen() -> Result<LocalDeviceContext> {
+    pub fn new_compatible_memory_dc_for_current_screen(
+        ui: &UiThread,
+    ) -> Result<LocalDeviceContext> {
         use std::ptr::null_mut;
         use winapi::um::wingdi::CreateCompatibleDC;
+        ui.check()?;
         let memdc = unsafe {
             let h = CreateCompatibleDC(null_mut());
             if h.is_null() {
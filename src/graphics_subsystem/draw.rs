@@ -38,8 +38,8 @@ impl<'a> ScopedDeviceContext<'a> {
         unsafe {
             if !booleanize(MoveToEx(
                 self.data_ref().raw_handle(),
-                pos.0.x,
-                pos.0.y,
+                pos.x,
+                pos.y,
                 null_mut(),
             )) {
                 return Error::last();
@@ -53,9 +53,9 @@ impl<'a> ScopedDeviceContext<'a> {
         unsafe {
             if !booleanize(MoveToEx(
                 self.data_ref().raw_handle(),
-                pos.0.x,
-                pos.0.y,
-                &mut pos.0,
+                pos.x,
+                pos.y,
+                pos.as_raw_mut_ptr(),
             )) {
                 return Error::last();
             }
@@ -66,7 +66,7 @@ impl<'a> ScopedDeviceContext<'a> {
     pub fn line_to(&mut self, pos: Point) -> Result<&mut Self> {
         use winapi::um::wingdi::LineTo;
         unsafe {
-            if !booleanize(LineTo(self.data_ref().raw_handle(), pos.0.x, pos.0.y)) {
+            if !booleanize(LineTo(self.data_ref().raw_handle(), pos.x, pos.y)) {
                 return Error::last();
             }
         }
@@ -77,7 +77,7 @@ impl<'a> ScopedDeviceContext<'a> {
         use winapi::um::wingdi::GetPixel;
         use winapi::um::wingdi::CLR_INVALID;
         let color = unsafe {
-            let r = GetPixel(self.data_ref().raw_handle(), pos.0.x, pos.0.y);
+            let r = GetPixel(self.data_ref().raw_handle(), pos.x, pos.y);
             r
         };
         if color == CLR_INVALID {
@@ -141,13 +141,13 @@ impl<'a> ScopedDeviceContext<'a> {
         unsafe {
             if !booleanize(BitBlt(
                 self.data_ref().raw_handle(),
-                dest_pos.0.x,
-                dest_pos.0.y,
-                size.0.cx,
-                size.0.cy,
+                dest_pos.x,
+                dest_pos.y,
+                size.cx,
+                size.cy,
                 src_dc.data_ref().raw_handle(),
-                src_pos.0.x,
-                src_pos.0.y,
+                src_pos.x,
+                src_pos.y,
                 rop.into(),
             )) {
                 return Error::last();
@@ -169,15 +169,15 @@ impl<'a> ScopedDeviceContext<'a> {
         unsafe {
             if !booleanize(TransparentBlt(
                 self.data_ref().raw_handle(),
-                dest_pos.0.x,
-                dest_pos.0.y,
-                dest_size.0.cx,
-                dest_size.0.cy,
+                dest_pos.x,
+                dest_pos.y,
+                dest_size.cx,
+                dest_size.cy,
                 src_dc.data_ref().raw_handle(),
-                src_pos.0.x,
-                src_pos.0.y,
-                src_size.0.cx,
-                src_size.0.cy,
+                src_pos.x,
+                src_pos.y,
+                src_size.cx,
+                src_size.cy,
                 key.into(),
             )) {
                 return Error::last();
@@ -1,27 +1,33 @@
 use derive_more::Into;
-use winapi;
-use winapi::shared::minwindef::DWORD;
 
 use crate::shared::clamp_i32_to_positive_i32;
 use crate::shared::clamp_isize_to_i32;
 use crate::shared::clamp_usize_to_positive_i32;
 use crate::shared::clamp_usize_to_positive_isize;
 
+#[cfg(windows)]
 pub mod device_context;
+#[cfg(windows)]
 pub mod draw;
+#[cfg(windows)]
 pub mod object;
 
-#[derive(Copy, Clone, Into)]
-pub struct Point(winapi::shared::windef::POINT);
+/// A point in logical units, layout-compatible with `POINT`.
+#[repr(C)]
+#[derive(Copy, Clone, Default, Eq)]
+pub struct Point {
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+}
 
 impl Point {
-    pub const ORIGIN: Point = Point(winapi::shared::windef::POINT { x: 0, y: 0 });
+    pub const ORIGIN: Point = Point { x: 0, y: 0 };
 
     pub fn new(x: isize, y: isize) -> Self {
-        Point(winapi::shared::windef::POINT {
+        Point {
             x: clamp_isize_to_i32(x),
             y: clamp_isize_to_i32(y),
-        })
+        }
     }
 
     pub fn new_from(x: impl Into<isize>, y: impl Into<isize>) -> Self {
@@ -29,17 +35,17 @@ impl Point {
     }
 
     pub fn x(&self) -> isize {
-        self.0.x as _
+        self.x as _
     }
 
     pub fn y(&self) -> isize {
-        self.0.y as _
+        self.y as _
     }
 
     pub fn offset(&self, off_x: isize, off_y: isize) -> Self {
         Point::new(
-            (self.0.x as isize).saturating_add(off_x),
-            (self.0.y as isize).saturating_add(off_y),
+            (self.x as isize).saturating_add(off_x),
+            (self.y as isize).saturating_add(off_y),
         )
     }
 }
@@ -48,7 +54,7 @@ use std::fmt::{self, Debug, Formatter};
 
 impl PartialEq<Self> for Point {
     fn eq(&self, rhs: &Self) -> bool {
-        self.0.x == rhs.0.x && self.0.y == rhs.0.y
+        self.x == rhs.x && self.y == rhs.y
     }
 }
 
@@ -58,15 +64,20 @@ impl Debug for Point {
     }
 }
 
-#[derive(Copy, Clone, Into)]
-pub struct Size(pub(crate) winapi::shared::windef::SIZE);
+/// An extent in logical units, layout-compatible with `SIZE`.
+#[repr(C)]
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Size {
+    pub(crate) cx: i32,
+    pub(crate) cy: i32,
+}
 
 impl Size {
     pub fn new(cx: usize, cy: usize) -> Self {
-        Size(winapi::shared::windef::SIZE {
+        Size {
             cx: clamp_usize_to_positive_i32(cx),
             cy: clamp_usize_to_positive_i32(cy),
-        })
+        }
     }
 
     pub fn new_from(cx: impl Into<usize>, cy: impl Into<usize>) -> Self {
@@ -74,11 +85,11 @@ impl Size {
     }
 
     pub fn cx(&self) -> usize {
-        self.0.cx as _
+        self.cx as _
     }
 
     pub fn cy(&self) -> usize {
-        self.0.cy as _
+        self.cy as _
     }
 }
 
@@ -88,162 +99,170 @@ impl Debug for Size {
     }
 }
 
-#[derive(Copy, Clone)]
+/// A rectangle in logical units, layout-compatible with `RECT`.
+#[repr(C)]
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
 pub struct Rect {
-    pos: Point,
-    size: Size,
+    left: i32,
+    top: i32,
+    right: i32,
+    bottom: i32,
 }
 
 impl Rect {
     pub fn new(pos: Point, size: Size) -> Self {
-        Rect { pos, size }
+        Rect {
+            left: pos.x,
+            top: pos.y,
+            right: pos.x.saturating_add(size.cx),
+            bottom: pos.y.saturating_add(size.cy),
+        }
     }
 
     pub fn size(&self) -> Size {
-        self.size
+        Size {
+            cx: clamp_i32_to_positive_i32(self.right.saturating_sub(self.left)),
+            cy: clamp_i32_to_positive_i32(self.bottom.saturating_sub(self.top)),
+        }
     }
 
     pub fn x(&self) -> isize {
-        self.pos.0.x as _
+        self.left as _
     }
 
     pub fn y(&self) -> isize {
-        self.pos.0.y as _
+        self.top as _
     }
 
     pub fn cx(&self) -> usize {
-        self.size.0.cx as _
+        self.size().cx()
     }
 
     pub fn cy(&self) -> usize {
-        self.size.0.cy as _
+        self.size().cy()
     }
 
     pub fn top_left(&self) -> Point {
-        self.pos
+        Point {
+            x: self.left,
+            y: self.top,
+        }
     }
 
     pub fn bottom_left(&self) -> Point {
-        Point::new(
-            self.pos.x(),
-            self.pos
-                .y()
-                .saturating_add(clamp_usize_to_positive_isize(self.size.cy())),
-        )
+        Point {
+            x: self.left,
+            y: self.bottom,
+        }
     }
+
     pub fn top_right(&self) -> Point {
-        Point::new(
-            self.pos
-                .x()
-                .saturating_add(clamp_usize_to_positive_isize(self.size.cx())),
-            self.pos.y(),
-        )
+        Point {
+            x: self.right,
+            y: self.top,
+        }
     }
+
     pub fn bottom_right(&self) -> Point {
-        Point::new(
-            self.pos
-                .x()
-                .saturating_add(clamp_usize_to_positive_isize(self.size.cx())),
-            self.pos
-                .y()
-                .saturating_add(clamp_usize_to_positive_isize(self.size.cy())),
-        )
+        Point {
+            x: self.right,
+            y: self.bottom,
+        }
     }
 
     pub fn deflate(&self, distance: usize) -> Self {
-        Rect {
-            pos: Point::new(
-                self.pos
-                    .x()
+        Rect::new(
+            Point::new(
+                self.x()
                     .saturating_sub(clamp_usize_to_positive_isize(distance)),
-                self.pos
-                    .y()
+                self.y()
                     .saturating_sub(clamp_usize_to_positive_isize(distance)),
             ),
-            size: Size::new(
-                self.size
-                    .cx()
-                    .saturating_add(distance.saturating_add(distance)),
-                self.size
-                    .cy()
-                    .saturating_add(distance.saturating_add(distance)),
+            Size::new(
+                self.cx().saturating_add(distance.saturating_add(distance)),
+                self.cy().saturating_add(distance.saturating_add(distance)),
             ),
-        }
+        )
     }
 
     pub fn contains(&self, pt: Point) -> bool {
-        let bottom_right = self.bottom_right();
-        if pt.0.x >= self.pos.0.x && pt.0.x < bottom_right.0.x {
-            if pt.0.y >= self.pos.0.y && pt.0.y < bottom_right.0.y {
-                return true;
-            }
-        }
-        false
+        pt.x >= self.left && pt.x < self.right && pt.y >= self.top && pt.y < self.bottom
     }
 }
 
-use winapi::shared::windef::RECT;
-impl From<RECT> for Rect {
-    fn from(v: RECT) -> Self {
-        Self {
-            pos: Point::new(v.left as isize, v.top as isize),
-            size: Size::new(
-                clamp_i32_to_positive_i32(v.right - v.left) as usize,
-                clamp_i32_to_positive_i32(v.bottom - v.top) as usize,
-            ),
-        }
-    }
-}
-
-impl From<Rect> for RECT {
-    fn from(v: Rect) -> Self {
-        let left_top = v.top_left();
-        let right_bottom = v.bottom_right();
-        Self {
-            left: left_top.0.x,
-            right: right_bottom.0.x,
-            top: left_top.0.y,
-            bottom: right_bottom.0.y,
-        }
+impl Debug for Rect {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "Rect(x={:?}, y={:?}, w={:?}, h={:?})",
+            self.x(),
+            self.y(),
+            self.cx(),
+            self.cy()
+        )
     }
 }
 
-#[derive(Copy, Clone, Into, PartialEq)]
-pub struct RGBColor(winapi::shared::windef::COLORREF);
+/// A color value, layout-compatible with `COLORREF`.
+#[repr(transparent)]
+#[derive(Copy, Clone, Into, PartialEq, Eq, Hash)]
+pub struct RGBColor(u32);
 
-macro_rules! winapi_rgb_value {
+macro_rules! rgb_value {
     ($r:expr, $g:expr, $b:expr) => {
-        $r as ::winapi::shared::windef::COLORREF
-            | (($g as ::winapi::shared::windef::COLORREF) << 8)
-            | (($b as ::winapi::shared::windef::COLORREF) << 16)
+        $r as u32 | (($g as u32) << 8) | (($b as u32) << 16)
     };
 }
 
 impl RGBColor {
-    pub const BLACK: RGBColor = RGBColor(winapi_rgb_value!(0, 0, 0));
-    pub const MAROON: RGBColor = RGBColor(winapi_rgb_value!(128, 0, 0));
-    pub const GREEN: RGBColor = RGBColor(winapi_rgb_value!(0, 128, 0));
-    pub const NAVY: RGBColor = RGBColor(winapi_rgb_value!(0, 0, 128));
-    pub const OLIVE: RGBColor = RGBColor(winapi_rgb_value!(128, 128, 0));
-    pub const TEAL: RGBColor = RGBColor(winapi_rgb_value!(0, 128, 128));
-    pub const PURPLE: RGBColor = RGBColor(winapi_rgb_value!(128, 0, 128));
-    pub const GRAY: RGBColor = RGBColor(winapi_rgb_value!(128, 128, 128));
-    pub const SILVER: RGBColor = RGBColor(winapi_rgb_value!(192, 192, 192));
-    pub const RED: RGBColor = RGBColor(winapi_rgb_value!(255, 0, 0));
-    pub const LIME: RGBColor = RGBColor(winapi_rgb_value!(0, 255, 0));
-    pub const BLUE: RGBColor = RGBColor(winapi_rgb_value!(0, 0, 255));
-    pub const YELLOW: RGBColor = RGBColor(winapi_rgb_value!(255, 255, 0));
-    pub const AQUA: RGBColor = RGBColor(winapi_rgb_value!(0, 255, 255));
-    pub const FUCHSIA: RGBColor = RGBColor(winapi_rgb_value!(255, 0, 255));
-    pub const WHITE: RGBColor = RGBColor(winapi_rgb_value!(255, 255, 255));
+    pub const BLACK: RGBColor = RGBColor(rgb_value!(0, 0, 0));
+    pub const MAROON: RGBColor = RGBColor(rgb_value!(128, 0, 0));
+    pub const GREEN: RGBColor = RGBColor(rgb_value!(0, 128, 0));
+    pub const NAVY: RGBColor = RGBColor(rgb_value!(0, 0, 128));
+    pub const OLIVE: RGBColor = RGBColor(rgb_value!(128, 128, 0));
+    pub const TEAL: RGBColor = RGBColor(rgb_value!(0, 128, 128));
+    pub const PURPLE: RGBColor = RGBColor(rgb_value!(128, 0, 128));
+    pub const GRAY: RGBColor = RGBColor(rgb_value!(128, 128, 128));
+    pub const SILVER: RGBColor = RGBColor(rgb_value!(192, 192, 192));
+    pub const RED: RGBColor = RGBColor(rgb_value!(255, 0, 0));
+    pub const LIME: RGBColor = RGBColor(rgb_value!(0, 255, 0));
+    pub const BLUE: RGBColor = RGBColor(rgb_value!(0, 0, 255));
+    pub const YELLOW: RGBColor = RGBColor(rgb_value!(255, 255, 0));
+    pub const AQUA: RGBColor = RGBColor(rgb_value!(0, 255, 255));
+    pub const FUCHSIA: RGBColor = RGBColor(rgb_value!(255, 0, 255));
+    pub const WHITE: RGBColor = RGBColor(rgb_value!(255, 255, 255));
 
     pub fn new(red: u8, green: u8, blue: u8) -> RGBColor {
-        RGBColor(winapi_rgb_value!(red, green, blue))
+        RGBColor(rgb_value!(red, green, blue))
+    }
+
+    pub fn red(&self) -> u8 {
+        self.0 as u8
+    }
+
+    pub fn green(&self) -> u8 {
+        (self.0 >> 8) as u8
+    }
+
+    pub fn blue(&self) -> u8 {
+        (self.0 >> 16) as u8
+    }
+}
+
+impl Debug for RGBColor {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "RGBColor(r={:?}, g={:?}, b={:?})",
+            self.red(),
+            self.green(),
+            self.blue()
+        )
     }
 }
 
 #[derive(Copy, Clone, Into)]
-pub struct BinaryROP(winapi::ctypes::c_int);
+pub struct BinaryROP(i32);
 
 #[allow(non_upper_case_globals)]
 impl BinaryROP {
@@ -283,7 +302,7 @@ impl BinaryROP {
 }
 
 #[derive(Copy, Clone, Into)]
-pub struct TenaryROP(winapi::shared::minwindef::DWORD);
+pub struct TenaryROP(u32);
 
 #[allow(non_upper_case_globals)]
 impl TenaryROP {
@@ -577,3 +596,134 @@ impl TenaryROP {
     pub const WHITENESS: TenaryROP =
         TenaryROP((Self::INTERNAL_OP_1.0 as u32) << 16 | Self::INTERNAL_OP_1.1 as u32);
 }
+
+#[cfg(windows)]
+mod conversions {
+    use super::{Point, RGBColor, Rect, Size};
+    use std::mem::{align_of, size_of};
+    use winapi::shared::windef::{COLORREF, POINT, RECT, SIZE};
+
+    const _: () = assert!(size_of::<Point>() == size_of::<POINT>());
+    const _: () = assert!(align_of::<Point>() == align_of::<POINT>());
+    const _: () = assert!(size_of::<Size>() == size_of::<SIZE>());
+    const _: () = assert!(align_of::<Size>() == align_of::<SIZE>());
+    const _: () = assert!(size_of::<Rect>() == size_of::<RECT>());
+    const _: () = assert!(align_of::<Rect>() == align_of::<RECT>());
+    const _: () = assert!(size_of::<RGBColor>() == size_of::<COLORREF>());
+
+    impl From<POINT> for Point {
+        fn from(v: POINT) -> Self {
+            Point { x: v.x, y: v.y }
+        }
+    }
+
+    impl From<Point> for POINT {
+        fn from(v: Point) -> Self {
+            POINT { x: v.x, y: v.y }
+        }
+    }
+
+    impl Point {
+        pub(crate) fn as_raw_mut_ptr(&mut self) -> *mut POINT {
+            self as *mut Point as *mut POINT
+        }
+    }
+
+    impl From<SIZE> for Size {
+        fn from(v: SIZE) -> Self {
+            Size {
+                cx: crate::shared::clamp_i32_to_positive_i32(v.cx),
+                cy: crate::shared::clamp_i32_to_positive_i32(v.cy),
+            }
+        }
+    }
+
+    impl From<Size> for SIZE {
+        fn from(v: Size) -> Self {
+            SIZE { cx: v.cx, cy: v.cy }
+        }
+    }
+
+    impl From<RECT> for Rect {
+        fn from(v: RECT) -> Self {
+            Rect {
+                left: v.left,
+                top: v.top,
+                right: v.right,
+                bottom: v.bottom,
+            }
+        }
+    }
+
+    impl From<Rect> for RECT {
+        fn from(v: Rect) -> Self {
+            RECT {
+                left: v.left,
+                top: v.top,
+                right: v.right,
+                bottom: v.bottom,
+            }
+        }
+    }
+
+    impl Rect {
+        pub(crate) fn as_raw_ptr(&self) -> *const RECT {
+            self as *const Rect as *const RECT
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points_clamp_and_saturate() {
+        let p = Point::new(isize::MAX, -5);
+        assert_eq!((p.x(), p.y()), (i32::MAX as isize, -5));
+        assert_eq!(p.offset(1, 2), Point::new(i32::MAX as isize, -3));
+        assert_eq!(Point::new_from(3i8, 4i16), Point::new(3, 4));
+        assert_eq!(Point::default(), Point::ORIGIN);
+    }
+
+    #[test]
+    fn sizes_clamp_to_positive_i32() {
+        let s = Size::new(usize::MAX, 7);
+        assert_eq!((s.cx(), s.cy()), (i32::MAX as usize, 7));
+        assert_eq!(format!("{:?}", Size::new(2, 3)), "Size(w=2, h=3)");
+    }
+
+    #[test]
+    fn rects_expose_corners_and_contain_half_open() {
+        let r = Rect::new(Point::new(10, 20), Size::new(30, 40));
+        assert_eq!((r.x(), r.y(), r.cx(), r.cy()), (10, 20, 30, 40));
+        assert_eq!(r.top_left(), Point::new(10, 20));
+        assert_eq!(r.top_right(), Point::new(40, 20));
+        assert_eq!(r.bottom_left(), Point::new(10, 60));
+        assert_eq!(r.bottom_right(), Point::new(40, 60));
+        assert!(r.contains(Point::new(10, 20)));
+        assert!(r.contains(Point::new(39, 59)));
+        assert!(!r.contains(Point::new(40, 59)));
+        assert!(!r.contains(Point::new(39, 60)));
+        assert_eq!(format!("{:?}", r), "Rect(x=10, y=20, w=30, h=40)");
+    }
+
+    #[test]
+    fn rects_saturate_at_the_edge() {
+        let r = Rect::new(Point::new(i32::MAX as isize - 5, 0), Size::new(10, 10));
+        assert_eq!(r.cx(), 5);
+        assert_eq!(r.bottom_right(), Point::new(i32::MAX as isize, 10));
+    }
+
+    #[test]
+    fn colors_pack_as_colorref() {
+        let c = RGBColor::new(0x12, 0x34, 0x56);
+        assert_eq!(u32::from(c), 0x0056_3412);
+        assert_eq!((c.red(), c.green(), c.blue()), (0x12, 0x34, 0x56));
+        assert_eq!(RGBColor::new(128, 0, 0), RGBColor::MAROON);
+        assert_eq!(
+            format!("{:?}", RGBColor::AQUA),
+            "RGBColor(r=0, g=255, b=255)"
+        );
+    }
+}
@@ -1,7 +1,5 @@
 use derive_more::Into;
 use log::warn;
-use std::cell::Cell;
-use std::rc::Rc;
 
 use winapi::ctypes::c_int;
 use winapi::shared::minwindef::HRGN;
@@ -48,7 +46,13 @@ impl ManagedData for PenInner {
     }
 }
 
+// GDI objects are not tied to the thread that created them.
+unsafe impl Send for PenInner {}
+unsafe impl Sync for PenInner {}
+
 pub type Pen = ManagedEntity<PenInner, strategy::LocalRc<'static>>;
+/// A `Pen` whose clones may be moved to other threads.
+pub type SharedPen = ManagedEntity<PenInner, strategy::LocalArc<'static>>;
 
 #[derive(Clone, Copy, Into)]
 pub struct PenStyle(c_int);
@@ -94,6 +98,14 @@ impl PenBuilder {
     }
 
     pub fn create(self) -> Result<Pen> {
+        Ok(strategy::LocalRc::attached_entity(self.create_inner()?))
+    }
+
+    pub fn create_shared(self) -> Result<SharedPen> {
+        Ok(strategy::LocalArc::attached_entity(self.create_inner()?))
+    }
+
+    fn create_inner(self) -> Result<PenInner> {
         use winapi::um::wingdi::CreatePen;
         let pen = unsafe {
             let h = CreatePen(
@@ -106,12 +118,16 @@ impl PenBuilder {
             }
             PenInner(h)
         };
-        Ok(strategy::LocalRc::attached_entity(pen))
+        Ok(pen)
     }
 }
 
 impl<'a> ScopedDeviceContext<'a> {
-    pub fn select_pen(&mut self, pen: Pen) -> Result<&mut Self> {
+    /// Keeps `pen`, shared or not, alive while it is selected.
+    pub fn select_pen<S>(&mut self, pen: ManagedEntity<PenInner, S>) -> Result<&mut Self>
+    where
+        S: ManagedStrategy + 'static,
+    {
         use winapi::um::wingdi::SelectObject;
         let old_pen = unsafe {
             let h = SelectObject(
@@ -128,16 +144,162 @@ impl<'a> ScopedDeviceContext<'a> {
     }
 }
 
+#[derive(Clone)]
 pub struct BrushInner(HBRUSH);
 
+impl BrushInner {
+    pub fn raw_handle(&self) -> HBRUSH {
+        self.0
+    }
+
+    fn new_solid(color: RGBColor) -> Result<Self> {
+        use winapi::um::wingdi::CreateSolidBrush;
+        let brush = unsafe {
+            let h = CreateSolidBrush(color.into());
+            if h.is_null() {
+                return Error::last();
+            }
+            BrushInner(h)
+        };
+        Ok(brush)
+    }
+}
+
+impl ManagedData for BrushInner {
+    fn share(&self) -> Self {
+        self.clone()
+    }
+
+    fn delete(&mut self) {
+        use winapi::um::wingdi::DeleteObject;
+        unsafe {
+            let succeeded = booleanize(DeleteObject(self.raw_handle() as _));
+            if !succeeded {
+                warn!(target: "apiw", "Failed to cleanup {}, last error: {:?}", "Brush", Error::last::<()>());
+            }
+        }
+    }
+}
+
+unsafe impl Send for BrushInner {}
+unsafe impl Sync for BrushInner {}
+
 pub type Brush = ManagedEntity<BrushInner, strategy::LocalRc<'static>>;
+pub type SharedBrush = ManagedEntity<BrushInner, strategy::LocalArc<'static>>;
+
+impl Brush {
+    pub fn new_solid(color: RGBColor) -> Result<Brush> {
+        BrushInner::new_solid(color).map(strategy::LocalRc::attached_entity)
+    }
+}
 
+impl SharedBrush {
+    pub fn new_solid(color: RGBColor) -> Result<SharedBrush> {
+        BrushInner::new_solid(color).map(strategy::LocalArc::attached_entity)
+    }
+}
+
+#[derive(Clone)]
 pub struct FontInner(HFONT);
 
+impl FontInner {
+    pub fn raw_handle(&self) -> HFONT {
+        self.0
+    }
+}
+
+impl ManagedData for FontInner {
+    fn share(&self) -> Self {
+        self.clone()
+    }
+
+    fn delete(&mut self) {
+        use winapi::um::wingdi::DeleteObject;
+        unsafe {
+            let succeeded = booleanize(DeleteObject(self.raw_handle() as _));
+            if !succeeded {
+                warn!(target: "apiw", "Failed to cleanup {}, last error: {:?}", "Font", Error::last::<()>());
+            }
+        }
+    }
+}
+
+unsafe impl Send for FontInner {}
+unsafe impl Sync for FontInner {}
+
 pub type Font = ManagedEntity<FontInner, strategy::LocalRc<'static>>;
+pub type SharedFont = ManagedEntity<FontInner, strategy::LocalArc<'static>>;
+
+#[derive(Default)]
+pub struct FontBuilder {
+    face_name: String,
+    height: i32,
+    weight: i32,
+    italic: bool,
+}
+
+impl FontBuilder {
+    pub fn new() -> Self {
+        FontBuilder::default()
+    }
+
+    /// At most 31 UTF-16 code units. Empty picks a default face.
+    pub fn face_name(mut self, face_name: &str) -> Self {
+        self.face_name = face_name.to_owned();
+        self
+    }
+
+    /// In logical units; negative for the character height rather than the
+    /// cell height, zero for a default height.
+    pub fn height(mut self, height: i32) -> Self {
+        self.height = height;
+        self
+    }
+
+    /// `FW_*`, 0 to 1000; zero for a default weight.
+    pub fn weight(mut self, weight: i32) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    pub fn italic(mut self, italic: bool) -> Self {
+        self.italic = italic;
+        self
+    }
+
+    pub fn create(self) -> Result<Font> {
+        Ok(strategy::LocalRc::attached_entity(self.create_inner()?))
+    }
+
+    pub fn create_shared(self) -> Result<SharedFont> {
+        Ok(strategy::LocalArc::attached_entity(self.create_inner()?))
+    }
+
+    fn create_inner(self) -> Result<FontInner> {
+        use winapi::shared::winerror::ERROR_INVALID_PARAMETER;
+        use winapi::um::wingdi::{CreateFontIndirectW, LOGFONTW};
+        let mut log_font: LOGFONTW = unsafe { std::mem::zeroed() };
+        let face_name: Vec<u16> = self.face_name.encode_utf16().collect();
+        if face_name.len() >= log_font.lfFaceName.len() {
+            return Err(Error::from_code(ERROR_INVALID_PARAMETER));
+        }
+        log_font.lfFaceName[..face_name.len()].copy_from_slice(&face_name);
+        log_font.lfHeight = self.height;
+        log_font.lfWeight = self.weight;
+        log_font.lfItalic = self.italic as u8;
+        let font = unsafe {
+            let h = CreateFontIndirectW(&log_font);
+            if h.is_null() {
+                return Error::last();
+            }
+            FontInner(h)
+        };
+        Ok(font)
+    }
+}
 
 #[derive(Clone)]
-pub struct BitmapInner(HBITMAP, Rc<Cell<bool>>);
+pub struct BitmapInner(HBITMAP);
 
 impl BitmapInner {
     pub fn raw_handle(&self) -> HBITMAP {
@@ -161,10 +323,14 @@ impl ManagedData for BitmapInner {
     }
 }
 
+unsafe impl Send for BitmapInner {}
+unsafe impl Sync for BitmapInner {}
+
 pub type Bitmap = ManagedEntity<BitmapInner, strategy::LocalRc<'static>>;
+pub type SharedBitmap = ManagedEntity<BitmapInner, strategy::LocalArc<'static>>;
 
-impl Bitmap {
-    pub fn load_from_resource_id(id: WORD) -> Result<Bitmap> {
+impl BitmapInner {
+    fn load_from_resource_id(id: WORD) -> Result<Self> {
         use crate::windows_subsystem::ResourceIDOrIDString;
         use winapi::um::winuser::LoadBitmapW;
         let resource = ResourceIDOrIDString::ID(id);
@@ -173,14 +339,30 @@ impl Bitmap {
             if h.is_null() {
                 return Error::last();
             }
-            BitmapInner(h, Rc::new(Cell::new(false)))
+            BitmapInner(h)
         };
-        Ok(strategy::LocalRc::attached_entity(bitmap))
+        Ok(bitmap)
+    }
+}
+
+impl Bitmap {
+    pub fn load_from_resource_id(id: WORD) -> Result<Bitmap> {
+        BitmapInner::load_from_resource_id(id).map(strategy::LocalRc::attached_entity)
+    }
+}
+
+impl SharedBitmap {
+    pub fn load_from_resource_id(id: WORD) -> Result<SharedBitmap> {
+        BitmapInner::load_from_resource_id(id).map(strategy::LocalArc::attached_entity)
     }
 }
 
 impl<'a> ScopedDeviceContext<'a> {
-    pub fn select_bitmap(&mut self, bitmap: Bitmap) -> Result<&mut Self> {
+    /// Keeps `bitmap`, shared or not, alive while it is selected.
+    pub fn select_bitmap<S>(&mut self, bitmap: ManagedEntity<BitmapInner, S>) -> Result<&mut Self>
+    where
+        S: ManagedStrategy + 'static,
+    {
         use winapi::um::wingdi::SelectObject;
         let old_pen = unsafe {
             let h = SelectObject(
@@ -197,9 +379,83 @@ impl<'a> ScopedDeviceContext<'a> {
     }
 }
 
-pub struct PaletteInner(HPALETTE); //FIXME
+#[derive(Clone)]
+pub struct PaletteInner(HPALETTE);
+
+impl PaletteInner {
+    pub fn raw_handle(&self) -> HPALETTE {
+        self.0
+    }
+
+    fn from_colors(colors: &[RGBColor]) -> Result<Self> {
+        use std::mem::size_of;
+        use winapi::shared::winerror::ERROR_INVALID_PARAMETER;
+        use winapi::um::wingdi::{CreatePalette, LOGPALETTE, PALETTEENTRY};
+        if colors.is_empty() || colors.len() > usize::from(WORD::MAX) {
+            return Err(Error::from_code(ERROR_INVALID_PARAMETER));
+        }
+        // LOGPALETTE ends in a variable-length array; the header is the
+        // size of one entry, and u32 is aligned enough for both.
+        const _: () = assert!(size_of::<PALETTEENTRY>() == size_of::<u32>());
+        let mut buffer = vec![0u32; 1 + colors.len()];
+        let palette = unsafe {
+            let log_palette = buffer.as_mut_ptr() as *mut LOGPALETTE;
+            (*log_palette).palVersion = 0x300;
+            (*log_palette).palNumEntries = colors.len() as WORD;
+            let entries = (*log_palette).palPalEntry.as_mut_ptr();
+            for (i, color) in colors.iter().enumerate() {
+                entries.add(i).write(PALETTEENTRY {
+                    peRed: color.red(),
+                    peGreen: color.green(),
+                    peBlue: color.blue(),
+                    peFlags: 0,
+                });
+            }
+            let h = CreatePalette(log_palette);
+            if h.is_null() {
+                return Error::last();
+            }
+            PaletteInner(h)
+        };
+        Ok(palette)
+    }
+}
+
+impl ManagedData for PaletteInner {
+    fn share(&self) -> Self {
+        self.clone()
+    }
+
+    fn delete(&mut self) {
+        use winapi::um::wingdi::DeleteObject;
+        unsafe {
+            let succeeded = booleanize(DeleteObject(self.raw_handle() as _));
+            if !succeeded {
+                warn!(target: "apiw", "Failed to cleanup {}, last error: {:?}", "Palette", Error::last::<()>());
+            }
+        }
+    }
+}
+
+unsafe impl Send for PaletteInner {}
+unsafe impl Sync for PaletteInner {}
 
 pub type Palette = ManagedEntity<PaletteInner, strategy::LocalRc<'static>>;
+pub type SharedPalette = ManagedEntity<PaletteInner, strategy::LocalArc<'static>>;
+
+impl Palette {
+    /// Fails with `ERROR_INVALID_PARAMETER` for no colors or more than
+    /// 65535.
+    pub fn from_colors(colors: &[RGBColor]) -> Result<Palette> {
+        PaletteInner::from_colors(colors).map(strategy::LocalRc::attached_entity)
+    }
+}
+
+impl SharedPalette {
+    pub fn from_colors(colors: &[RGBColor]) -> Result<SharedPalette> {
+        PaletteInner::from_colors(colors).map(strategy::LocalArc::attached_entity)
+    }
+}
 
 pub struct RegionInner(HRGN);
 
@@ -230,3 +486,31 @@ impl<'a, T: Handle> Deref for Temporary<'a, T> {
     }
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    use crate::graphics_subsystem::device_context::LocalDeviceContext;
+    use crate::windows_subsystem::ui_thread::UiThread;
+
+    #[test]
+    fn shared_pen_can_be_selected_on_another_thread() {
+        let pen = PenBuilder::new()
+            .width(2)
+            .color(RGBColor::new(0x12, 0x34, 0x56))
+            .create_shared()
+            .unwrap();
+        let sent = pen.clone();
+        thread::spawn(move || {
+            let ui = UiThread::current();
+            let mut dc =
+                LocalDeviceContext::new_compatible_memory_dc_for_current_screen(&ui).unwrap();
+            dc.select_pen(sent).unwrap();
+        })
+        .join()
+        .unwrap();
+        drop(pen);
+    }
+}
@@ -1,18 +1,14 @@
 #![allow(unused)]
 
-#[cfg(windows)]
 pub mod shared;
 
-#[cfg(windows)]
 pub mod windows_subsystem;
 
-#[cfg(windows)]
 pub mod graphics_subsystem;
 
 #[cfg(windows)]
 pub mod application_support_functions;
 
-#[cfg(windows)]
 pub mod extensions;
 
 #[cfg(windows)]
@@ -21,5 +17,6 @@ pub mod full_windows_api {
     pub use winapi::*;
 }
 
+pub use crate::shared::{Error, Result};
 #[cfg(windows)]
-pub use crate::shared::{maybe_last_error, internal_error, Error, Result};
+pub use crate::shared::{maybe_last_error, internal_error};
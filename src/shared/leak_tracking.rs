@@ -0,0 +1,182 @@
+//! Bookkeeping of live managed entities, for finding handle leaks.
+//!
+//! With the `leak-tracking` feature enabled, every handle a `ManagedEntity`
+//! owns is registered in the global registry with the backtrace of its
+//! creation, and unregistered when it is deleted. Clones sharing a handle
+//! through `LocalRc` or `LocalArc` count once. Entities that merely wrap
+//! foreign handles are not tracked.
+
+use log::warn;
+use std::backtrace::Backtrace;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::shared::ManagedData;
+
+/// Identifies one registered entity.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct LeakId(u64);
+
+struct LiveEntry {
+    type_name: &'static str,
+    backtrace: Backtrace,
+}
+
+/// An entity that is still alive.
+#[derive(Debug)]
+pub struct Leak {
+    pub type_name: &'static str,
+    pub backtrace: String,
+}
+
+pub struct LeakRegistry {
+    next_id: AtomicU64,
+    live: Mutex<BTreeMap<u64, LiveEntry>>,
+}
+
+/// `PenInner` is reported as `Pen`, `WindowInner` as `Window`.
+fn short_type_name(full: &'static str) -> &'static str {
+    let path = full.split('<').next().unwrap_or(full);
+    let name = path.rsplit("::").next().unwrap_or(path);
+    name.strip_suffix("Inner")
+        .filter(|s| !s.is_empty())
+        .unwrap_or(name)
+}
+
+impl LeakRegistry {
+    pub const fn new() -> Self {
+        LeakRegistry {
+            next_id: AtomicU64::new(0),
+            live: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn live(&self) -> MutexGuard<'_, BTreeMap<u64, LiveEntry>> {
+        // a panic while holding the lock cannot leave the map inconsistent.
+        self.live.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn register<D: ManagedData>(&self) -> LeakId {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let entry = LiveEntry {
+            type_name: short_type_name(std::any::type_name::<D>()),
+            backtrace: Backtrace::force_capture(),
+        };
+        self.live().insert(id, entry);
+        LeakId(id)
+    }
+
+    pub fn unregister(&self, id: LeakId) {
+        self.live().remove(&id.0);
+    }
+
+    /// Number of live entities of each type.
+    pub fn live_counts(&self) -> BTreeMap<&'static str, usize> {
+        let mut counts = BTreeMap::new();
+        for entry in self.live().values() {
+            *counts.entry(entry.type_name).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    pub fn live_count<D: ManagedData>(&self) -> usize {
+        let type_name = short_type_name(std::any::type_name::<D>());
+        self.live()
+            .values()
+            .filter(|entry| entry.type_name == type_name)
+            .count()
+    }
+
+    /// Live entities in creation order.
+    pub fn leaks(&self) -> Vec<Leak> {
+        self.live()
+            .values()
+            .map(|entry| Leak {
+                type_name: entry.type_name,
+                backtrace: entry.backtrace.to_string(),
+            })
+            .collect()
+    }
+
+    /// Logs every live entity as a warning and returns how many there were.
+    pub fn dump(&self) -> usize {
+        let leaks = self.leaks();
+        for leak in &leaks {
+            warn!(target: "apiw", "Leaked {}, created at:\n{}", leak.type_name, leak.backtrace);
+        }
+        leaks.len()
+    }
+}
+
+impl Default for LeakRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static REGISTRY: LeakRegistry = LeakRegistry::new();
+
+/// The registry `ManagedEntity` reports to.
+pub fn registry() -> &'static LeakRegistry {
+    &REGISTRY
+}
+
+/// Dumps the global registry when dropped. Keep one alive in `main` to
+/// report whatever is still alive at exit.
+#[must_use]
+pub struct DumpOnExit(());
+
+pub fn dump_on_exit() -> DumpOnExit {
+    DumpOnExit(())
+}
+
+impl Drop for DumpOnExit {
+    fn drop(&mut self) {
+        REGISTRY.dump();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct PenInner;
+    struct WindowInner;
+
+    impl ManagedData for PenInner {
+        fn share(&self) -> Self {
+            PenInner
+        }
+
+        fn delete(&mut self) {}
+    }
+
+    impl ManagedData for WindowInner {
+        fn share(&self) -> Self {
+            WindowInner
+        }
+
+        fn delete(&mut self) {}
+    }
+
+    #[test]
+    fn counts_live_entities_by_type() {
+        let registry = LeakRegistry::new();
+        let pen = registry.register::<PenInner>();
+        let _other_pen = registry.register::<PenInner>();
+        let window = registry.register::<WindowInner>();
+        assert_eq!(registry.live_count::<PenInner>(), 2);
+        assert_eq!(
+            registry.live_counts().into_iter().collect::<Vec<_>>(),
+            vec![("Pen", 2), ("Window", 1)]
+        );
+
+        registry.unregister(pen);
+        registry.unregister(window);
+        let leaks = registry.leaks();
+        assert_eq!(leaks.len(), 1);
+        assert_eq!(leaks[0].type_name, "Pen");
+        assert_eq!(registry.dump(), 1);
+    }
+}
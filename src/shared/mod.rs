@@ -1,12 +1,15 @@
+#[cfg(windows)]
 use std::ptr::null_mut;
-use winapi;
+#[cfg(windows)]
 use winapi::shared::minwindef::BOOL;
+#[cfg(windows)]
 use winapi::shared::minwindef::HINSTANCE;
-use wio::wide::ToWide;
 
+#[cfg(windows)]
 pub(crate) fn booleanize(v: BOOL) -> bool {
     v != 0
 }
+#[cfg(windows)]
 pub(crate) fn revert_booleanize(v: bool) -> BOOL {
     use winapi::shared::minwindef::{FALSE, TRUE};
     if v {
@@ -18,9 +21,8 @@ pub(crate) fn revert_booleanize(v: bool) -> BOOL {
 
 #[inline]
 pub(crate) fn clamp_usize_to_positive_isize(v: usize) -> isize {
-    use std::isize::MAX;
-    if v > MAX as _ {
-        MAX
+    if v > isize::MAX as _ {
+        isize::MAX
     } else {
         v as _
     }
@@ -28,11 +30,10 @@ pub(crate) fn clamp_usize_to_positive_isize(v: usize) -> isize {
 
 #[inline]
 pub(crate) fn clamp_isize_to_i32(v: isize) -> i32 {
-    use std::i32::{MAX, MIN};
-    if v < MIN as _ {
-        MIN
-    } else if v > MAX as _ {
-        MAX
+    if v < i32::MIN as _ {
+        i32::MIN
+    } else if v > i32::MAX as _ {
+        i32::MAX
     } else {
         v as _
     }
@@ -49,9 +50,8 @@ pub(crate) fn clamp_i32_to_positive_i32(v: i32) -> i32 {
 
 #[inline]
 pub(crate) fn clamp_usize_to_positive_i32(v: usize) -> i32 {
-    use std::i32::MAX;
-    if v > MAX as _ {
-        MAX
+    if v > i32::MAX as _ {
+        i32::MAX
     } else {
         v as _
     }
@@ -59,35 +59,40 @@ pub(crate) fn clamp_usize_to_positive_i32(v: usize) -> i32 {
 
 #[inline]
 pub(crate) fn clamp_isize_to_positive_i32(v: isize) -> i32 {
-    use std::i32::MAX;
     if v < 0 {
         0
-    } else if v > MAX as _ {
-        MAX
+    } else if v > i32::MAX as _ {
+        i32::MAX
     } else {
         v as _
     }
 }
 
+#[cfg(windows)]
 pub fn exe_cmd_show() -> winapi::ctypes::c_int {
     return winapi::um::winuser::SW_SHOW;
     // FIXME: This should be retrieved from GetStartupInfo().
     unimplemented!();
 }
 
+#[cfg(windows)]
 pub fn exe_instance() -> HINSTANCE {
     unsafe { winapi::um::libloaderapi::GetModuleHandleW(null_mut()) }
 }
 
-use winapi::shared::minwindef::DWORD;
-use winapi::um::errhandlingapi::GetLastError;
-
 #[derive(Clone, Copy, Debug)]
-pub struct Error(DWORD);
+pub struct Error(u32);
 
 impl Error {
     pub fn code(&self) -> u32 { self.0 }
+
+    pub(crate) fn from_code(code: u32) -> Self {
+        Error(code)
+    }
+
+    #[cfg(windows)]
     pub fn last<T>() -> Result<T> {
+        use winapi::um::errhandlingapi::GetLastError;
         Err(Error(unsafe { GetLastError() }))
     }
 }
@@ -99,6 +104,7 @@ pub struct CommDlgErr(pub(crate) u32);
 
 pub type CommDlgResult<T> = ::std::result::Result<T, CommDlgErr>;
 
+#[cfg(windows)]
 pub fn maybe_last_error<T, D: FnOnce() -> T>(f: D) -> Result<T> {
     let err = Error::last();
     let code = if let Err(ref err) = err {
@@ -113,15 +119,18 @@ pub fn maybe_last_error<T, D: FnOnce() -> T>(f: D) -> Result<T> {
     }
 }
 
+#[cfg(windows)]
 pub fn internal_error<T>() -> Result<T> {
     use winapi::shared::winerror::ERROR_INTERNAL_ERROR;
     Err(Error(ERROR_INTERNAL_ERROR))
 }
 
+#[cfg(windows)]
 pub trait OkOrLastError<T> {
     fn ok_or_last_error(self) -> Result<T>;
 }
 
+#[cfg(windows)]
 impl<T> OkOrLastError<T> for Option<T> {
     fn ok_or_last_error(self) -> Result<T> {
         use crate::shared::Error;
@@ -133,6 +142,7 @@ impl<T> OkOrLastError<T> for Option<T> {
     }
 }
 
+#[cfg(windows)]
 impl<T> OkOrLastError<*mut T> for *mut T {
     fn ok_or_last_error(self) -> Result<*mut T> {
         use crate::shared::Error;
@@ -144,6 +154,7 @@ impl<T> OkOrLastError<*mut T> for *mut T {
     }
 }
 
+#[cfg(windows)]
 impl<T> OkOrLastError<*const T> for *const T {
     fn ok_or_last_error(self) -> Result<*const T> {
         use crate::shared::Error;
@@ -155,6 +166,23 @@ impl<T> OkOrLastError<*const T> for *const T {
     }
 }
 
+pub mod leak_tracking;
+
+use std::cell::RefCell;
+
+/// Calls `f` with the contents of `cell`, or `reentered` if an outer call
+/// on this thread still has it borrowed.
+pub(crate) fn borrow_or_reentered<T: ?Sized, R>(
+    cell: &RefCell<T>,
+    f: impl FnOnce(&mut T) -> R,
+    reentered: impl FnOnce() -> R,
+) -> R {
+    match cell.try_borrow_mut() {
+        Ok(mut value) => f(&mut value),
+        Err(_) => reentered(),
+    }
+}
+
 pub trait ManagedStrategy {
     fn clean_up<D: ManagedData>(&mut self, data: &mut D);
 }
@@ -167,9 +195,26 @@ pub trait ManagedData {
 pub struct ManagedEntity<D: ManagedData, T: ManagedStrategy> {
     data: D,
     strategy: T,
+    #[cfg(feature = "leak-tracking")]
+    leak: Option<leak_tracking::LeakId>,
 }
 
 impl<D: ManagedData, T: ManagedStrategy> ManagedEntity<D, T> {
+    /// `tracked` registers this entity on its own; shared strategies track
+    /// their handle in the shared counter instead.
+    fn new(data: D, strategy: T, tracked: bool) -> Self {
+        ManagedEntity {
+            #[cfg(feature = "leak-tracking")]
+            leak: if tracked {
+                Some(leak_tracking::registry().register::<D>())
+            } else {
+                None
+            },
+            data,
+            strategy,
+        }
+    }
+
     pub(crate) fn data_ref(&self) -> &D {
         &self.data
     }
@@ -181,11 +226,17 @@ impl<D: ManagedData, T: ManagedStrategy> ManagedEntity<D, T> {
 
 impl<D: ManagedData, T: ManagedStrategy> Drop for ManagedEntity<D, T> {
     fn drop(&mut self) {
+        #[cfg(feature = "leak-tracking")]
+        {
+            if let Some(leak) = self.leak.take() {
+                leak_tracking::registry().unregister(leak);
+            }
+        }
         self.strategy.clean_up(&mut self.data)
     }
 }
 
-impl<'a, D: ManagedData + 'a> Clone for ManagedEntity<D, strategy::Foreign> {
+impl<D: ManagedData> Clone for ManagedEntity<D, strategy::Foreign> {
     fn clone(&self) -> Self {
         let foreign = self.strategy.clone();
         foreign.cloned_entity(&self.data)
@@ -206,29 +257,62 @@ impl<'a, D: ManagedData + 'a> Clone for ManagedEntity<D, strategy::LocalRc<'a>>
     }
 }
 
+impl<'a, D: ManagedData + 'a> Clone for ManagedEntity<D, strategy::LocalArc<'a>> {
+    fn clone(&self) -> Self {
+        let arc = self.strategy.clone();
+        arc.cloned_entity(&self.data)
+    }
+}
+
 pub mod strategy {
     use crate::shared::ManagedData;
     use crate::shared::ManagedEntity;
     use crate::shared::ManagedStrategy;
     use std::marker::PhantomData;
     use std::rc::Rc;
+    use std::sync::Arc;
+
+    #[cfg(feature = "leak-tracking")]
+    use crate::shared::leak_tracking;
+
+    /// Held in the shared counter, so that an underlying handle is tracked
+    /// once however many entities share it, and untracked when the last of
+    /// them cleans up.
+    struct SharedOwner {
+        #[cfg(feature = "leak-tracking")]
+        leak: leak_tracking::LeakId,
+    }
+
+    impl SharedOwner {
+        #[cfg_attr(
+            not(feature = "leak-tracking"),
+            allow(clippy::extra_unused_type_parameters)
+        )]
+        fn new<D: ManagedData>() -> Self {
+            SharedOwner {
+                #[cfg(feature = "leak-tracking")]
+                leak: leak_tracking::registry().register::<D>(),
+            }
+        }
+    }
+
+    #[cfg(feature = "leak-tracking")]
+    impl Drop for SharedOwner {
+        fn drop(&mut self) {
+            leak_tracking::registry().unregister(self.leak);
+        }
+    }
 
     #[derive(Clone)]
     pub struct Foreign;
 
     impl Foreign {
         pub fn attached_entity<D: ManagedData>(data: D) -> ManagedEntity<D, Self> {
-            ManagedEntity {
-                data,
-                strategy: Foreign,
-            }
+            ManagedEntity::new(data, Foreign, false)
         }
 
         pub fn cloned_entity<D: ManagedData>(self, data: &D) -> ManagedEntity<D, Self> {
-            ManagedEntity {
-                data: data.share(),
-                strategy: self,
-            }
+            ManagedEntity::new(data.share(), self, false)
         }
     }
 
@@ -243,17 +327,11 @@ pub mod strategy {
 
     impl<'a> Local<'a> {
         pub fn attached_entity<D: ManagedData + 'a>(data: D) -> ManagedEntity<D, Self> {
-            ManagedEntity {
-                data,
-                strategy: Local(PhantomData),
-            }
+            ManagedEntity::new(data, Local(PhantomData), true)
         }
 
         pub fn cloned_entity<D: ManagedData + 'a>(self, data: &D) -> ManagedEntity<D, Self> {
-            ManagedEntity {
-                data: data.share(),
-                strategy: self,
-            }
+            ManagedEntity::new(data.share(), self, true)
         }
     }
 
@@ -265,21 +343,16 @@ pub mod strategy {
     }
 
     #[derive(Clone)]
-    pub struct LocalRc<'a>(Option<Rc<()>>, PhantomData<&'a ()>);
+    pub struct LocalRc<'a>(Option<Rc<SharedOwner>>, PhantomData<&'a ()>);
 
     impl<'a> LocalRc<'a> {
         pub fn attached_entity<D: ManagedData + 'a>(data: D) -> ManagedEntity<D, Self> {
-            ManagedEntity {
-                data,
-                strategy: LocalRc(Some(Rc::new(())), PhantomData),
-            }
+            let owner = Rc::new(SharedOwner::new::<D>());
+            ManagedEntity::new(data, LocalRc(Some(owner), PhantomData), false)
         }
 
         pub fn cloned_entity<D: ManagedData + 'a>(self, data: &D) -> ManagedEntity<D, Self> {
-            ManagedEntity {
-                data: data.share(),
-                strategy: self,
-            }
+            ManagedEntity::new(data.share(), self, false)
         }
     }
 
@@ -294,8 +367,37 @@ pub mod strategy {
             }
         }
     }
+
+    /// Like `LocalRc`, but clones may live on other threads.
+    #[derive(Clone)]
+    pub struct LocalArc<'a>(Option<Arc<SharedOwner>>, PhantomData<&'a ()>);
+
+    impl<'a> LocalArc<'a> {
+        pub fn attached_entity<D: ManagedData + 'a>(data: D) -> ManagedEntity<D, Self> {
+            let owner = Arc::new(SharedOwner::new::<D>());
+            ManagedEntity::new(data, LocalArc(Some(owner), PhantomData), false)
+        }
+
+        pub fn cloned_entity<D: ManagedData + 'a>(self, data: &D) -> ManagedEntity<D, Self> {
+            ManagedEntity::new(data.share(), self, false)
+        }
+    }
+
+    impl<'a> ManagedStrategy for LocalArc<'a> {
+        fn clean_up<D: ManagedData>(&mut self, data: &mut D) {
+            // unlike a strong count check, `into_inner` picks exactly one
+            // last owner even when clones drop concurrently.
+            if let Some(counter) = self.0.take() {
+                if Arc::into_inner(counter).is_some() {
+                    data.delete()
+                }
+            }
+        }
+    }
 }
 
+use std::ffi::OsStr;
+
 #[derive(Clone, Default)]
 pub struct CWideString(Vec<u16>);
 
@@ -316,6 +418,10 @@ impl CWideString {
         }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn len_with_null(&self) -> usize {
         self.0.len()
     }
@@ -325,9 +431,16 @@ impl CWideString {
     }
 }
 
-impl<T: ToWide> From<T> for CWideString {
+impl<T: AsRef<OsStr>> From<T> for CWideString {
+    #[cfg(windows)]
     fn from(v: T) -> Self {
-        CWideString(v.to_wide_null())
+        use std::os::windows::ffi::OsStrExt;
+        CWideString(v.as_ref().encode_wide().chain(Some(0)).collect())
+    }
+
+    #[cfg(not(windows))]
+    fn from(v: T) -> Self {
+        CWideString(v.as_ref().to_string_lossy().encode_utf16().chain(Some(0)).collect())
     }
 }
 
@@ -338,7 +451,7 @@ impl CWideStringSeq {
         CWideStringSeq(data)
     }
 
-    pub fn iter_wide_null(&self) -> CWideStringSeqIter {
+    pub fn iter_wide_null(&self) -> CWideStringSeqIter<'_> {
         CWideStringSeqIter { seq: self, pos: 0 }
     }
 }
@@ -358,10 +471,90 @@ impl<'a> Iterator for CWideStringSeqIter<'a> {
                 .position(|&c| c == 0)
                 .expect("Data is inconsistent");
             let val = Some(&self.seq.0[(self.pos)..(self.pos + next_zero_pos + 1)]);
-            self.pos = self.pos + next_zero_pos + 1;
+            self.pos += next_zero_pos + 1;
             val
         } else {
             None
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct FakeData(Arc<AtomicUsize>);
+
+    impl ManagedData for FakeData {
+        fn share(&self) -> Self {
+            FakeData(self.0.clone())
+        }
+
+        fn delete(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn reentered_calls_fall_through() {
+        let cell = RefCell::new(Vec::new());
+        let outer = borrow_or_reentered(
+            &cell,
+            |log: &mut Vec<&str>| {
+                log.push("outer");
+                borrow_or_reentered(&cell, |_| "routed", || "forwarded")
+            },
+            || "forwarded",
+        );
+        assert_eq!(outer, "forwarded");
+        assert_eq!(borrow_or_reentered(&cell, |log| log.len(), || 0), 1);
+    }
+
+    #[test]
+    fn local_arc_deletes_once_after_last_clone() {
+        let deletes = Arc::new(AtomicUsize::new(0));
+        let entity = strategy::LocalArc::attached_entity(FakeData(deletes.clone()));
+        let clones: Vec<_> = (0..8).map(|_| entity.clone()).collect();
+        drop(entity);
+        let threads: Vec<_> = clones
+            .into_iter()
+            .map(|clone| std::thread::spawn(move || drop(clone)))
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(deletes.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "leak-tracking")]
+    #[test]
+    fn shared_clones_are_tracked_once() {
+        // a type of its own, as the registry is shared with other tests.
+        struct TrackedData;
+
+        impl ManagedData for TrackedData {
+            fn share(&self) -> Self {
+                TrackedData
+            }
+
+            fn delete(&mut self) {}
+        }
+
+        let registry = leak_tracking::registry();
+        let rc = strategy::LocalRc::attached_entity(TrackedData);
+        let arc = strategy::LocalArc::attached_entity(TrackedData);
+        let rc_clone = rc.clone();
+        let arc_clone = arc.clone();
+        assert_eq!(registry.live_count::<TrackedData>(), 2);
+
+        drop(rc);
+        drop(arc);
+        assert_eq!(registry.live_count::<TrackedData>(), 2);
+
+        drop(rc_clone);
+        drop(arc_clone);
+        assert_eq!(registry.live_count::<TrackedData>(), 0);
+    }
+}
@@ -0,0 +1,283 @@
+macro_rules! virtual_keys {
+    ($($name:ident = $value:expr,)*) => {
+        /// A virtual-key code (`VK_*`).
+        ///
+        /// `from_code` never produces `Other` for a code that has a named variant.
+        #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+        pub enum VirtualKey {
+            $($name,)*
+            Other(u8),
+        }
+
+        impl VirtualKey {
+            pub fn from_code(code: u8) -> Self {
+                match code {
+                    $($value => VirtualKey::$name,)*
+                    v => VirtualKey::Other(v),
+                }
+            }
+
+            pub fn code(&self) -> u8 {
+                match self {
+                    $(VirtualKey::$name => $value,)*
+                    VirtualKey::Other(v) => *v,
+                }
+            }
+        }
+    };
+}
+
+virtual_keys! {
+    LeftButton = 0x01,
+    RightButton = 0x02,
+    Cancel = 0x03,
+    MiddleButton = 0x04,
+    XButton1 = 0x05,
+    XButton2 = 0x06,
+    Backspace = 0x08,
+    Tab = 0x09,
+    Clear = 0x0C,
+    Enter = 0x0D,
+    Shift = 0x10,
+    Control = 0x11,
+    Alt = 0x12,
+    Pause = 0x13,
+    CapsLock = 0x14,
+    Kana = 0x15,
+    ImeOn = 0x16,
+    Junja = 0x17,
+    Final = 0x18,
+    Kanji = 0x19,
+    ImeOff = 0x1A,
+    Escape = 0x1B,
+    Convert = 0x1C,
+    NonConvert = 0x1D,
+    Accept = 0x1E,
+    ModeChange = 0x1F,
+    Space = 0x20,
+    PageUp = 0x21,
+    PageDown = 0x22,
+    End = 0x23,
+    Home = 0x24,
+    Left = 0x25,
+    Up = 0x26,
+    Right = 0x27,
+    Down = 0x28,
+    Select = 0x29,
+    Print = 0x2A,
+    Execute = 0x2B,
+    PrintScreen = 0x2C,
+    Insert = 0x2D,
+    Delete = 0x2E,
+    Help = 0x2F,
+    Digit0 = 0x30,
+    Digit1 = 0x31,
+    Digit2 = 0x32,
+    Digit3 = 0x33,
+    Digit4 = 0x34,
+    Digit5 = 0x35,
+    Digit6 = 0x36,
+    Digit7 = 0x37,
+    Digit8 = 0x38,
+    Digit9 = 0x39,
+    A = 0x41,
+    B = 0x42,
+    C = 0x43,
+    D = 0x44,
+    E = 0x45,
+    F = 0x46,
+    G = 0x47,
+    H = 0x48,
+    I = 0x49,
+    J = 0x4A,
+    K = 0x4B,
+    L = 0x4C,
+    M = 0x4D,
+    N = 0x4E,
+    O = 0x4F,
+    P = 0x50,
+    Q = 0x51,
+    R = 0x52,
+    S = 0x53,
+    T = 0x54,
+    U = 0x55,
+    V = 0x56,
+    W = 0x57,
+    X = 0x58,
+    Y = 0x59,
+    Z = 0x5A,
+    LeftWindows = 0x5B,
+    RightWindows = 0x5C,
+    Apps = 0x5D,
+    Sleep = 0x5F,
+    Numpad0 = 0x60,
+    Numpad1 = 0x61,
+    Numpad2 = 0x62,
+    Numpad3 = 0x63,
+    Numpad4 = 0x64,
+    Numpad5 = 0x65,
+    Numpad6 = 0x66,
+    Numpad7 = 0x67,
+    Numpad8 = 0x68,
+    Numpad9 = 0x69,
+    Multiply = 0x6A,
+    Add = 0x6B,
+    Separator = 0x6C,
+    Subtract = 0x6D,
+    Decimal = 0x6E,
+    Divide = 0x6F,
+    F1 = 0x70,
+    F2 = 0x71,
+    F3 = 0x72,
+    F4 = 0x73,
+    F5 = 0x74,
+    F6 = 0x75,
+    F7 = 0x76,
+    F8 = 0x77,
+    F9 = 0x78,
+    F10 = 0x79,
+    F11 = 0x7A,
+    F12 = 0x7B,
+    F13 = 0x7C,
+    F14 = 0x7D,
+    F15 = 0x7E,
+    F16 = 0x7F,
+    F17 = 0x80,
+    F18 = 0x81,
+    F19 = 0x82,
+    F20 = 0x83,
+    F21 = 0x84,
+    F22 = 0x85,
+    F23 = 0x86,
+    F24 = 0x87,
+    NumLock = 0x90,
+    ScrollLock = 0x91,
+    LeftShift = 0xA0,
+    RightShift = 0xA1,
+    LeftControl = 0xA2,
+    RightControl = 0xA3,
+    LeftAlt = 0xA4,
+    RightAlt = 0xA5,
+    BrowserBack = 0xA6,
+    BrowserForward = 0xA7,
+    BrowserRefresh = 0xA8,
+    BrowserStop = 0xA9,
+    BrowserSearch = 0xAA,
+    BrowserFavorites = 0xAB,
+    BrowserHome = 0xAC,
+    VolumeMute = 0xAD,
+    VolumeDown = 0xAE,
+    VolumeUp = 0xAF,
+    MediaNextTrack = 0xB0,
+    MediaPrevTrack = 0xB1,
+    MediaStop = 0xB2,
+    MediaPlayPause = 0xB3,
+    LaunchMail = 0xB4,
+    LaunchMediaSelect = 0xB5,
+    LaunchApp1 = 0xB6,
+    LaunchApp2 = 0xB7,
+    Oem1 = 0xBA,
+    OemPlus = 0xBB,
+    OemComma = 0xBC,
+    OemMinus = 0xBD,
+    OemPeriod = 0xBE,
+    Oem2 = 0xBF,
+    Oem3 = 0xC0,
+    Oem4 = 0xDB,
+    Oem5 = 0xDC,
+    Oem6 = 0xDD,
+    Oem7 = 0xDE,
+    Oem8 = 0xDF,
+    Oem102 = 0xE2,
+    ProcessKey = 0xE5,
+    Packet = 0xE7,
+    Attn = 0xF6,
+    CrSel = 0xF7,
+    ExSel = 0xF8,
+    EraseEof = 0xF9,
+    Play = 0xFA,
+    Zoom = 0xFB,
+    Pa1 = 0xFD,
+    OemClear = 0xFE,
+}
+
+/// Reassembles characters from the UTF-16 code units delivered by
+/// successive `WM_CHAR` messages.
+///
+/// A high surrogate is held until the following code unit arrives. A low
+/// surrogate without a preceding high surrogate yields U+FFFD, and a high
+/// surrogate that is not followed by a low surrogate is dropped.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct SurrogatePairCombiner {
+    pending_high: Option<u16>,
+}
+
+impl SurrogatePairCombiner {
+    pub fn new() -> Self {
+        SurrogatePairCombiner { pending_high: None }
+    }
+
+    pub fn push(&mut self, code_unit: u16) -> Option<char> {
+        match code_unit {
+            0xD800..=0xDBFF => {
+                self.pending_high = Some(code_unit);
+                None
+            }
+            0xDC00..=0xDFFF => {
+                let c = match self.pending_high.take() {
+                    Some(high) => {
+                        0x10000 + (((high as u32) - 0xD800) << 10) + ((code_unit as u32) - 0xDC00)
+                    }
+                    None => 0xFFFD,
+                };
+                std::char::from_u32(c)
+            }
+            _ => {
+                self.pending_high = None;
+                std::char::from_u32(code_unit as u32)
+            }
+        }
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.pending_high.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn virtual_key_codes_round_trip() {
+        for code in 0..=255u8 {
+            assert_eq!(VirtualKey::from_code(code).code(), code);
+        }
+        assert_eq!(VirtualKey::from_code(0x41), VirtualKey::A);
+        assert_eq!(VirtualKey::from_code(0x0D), VirtualKey::Enter);
+        assert_eq!(VirtualKey::from_code(0x87), VirtualKey::F24);
+        assert_eq!(VirtualKey::from_code(0x07), VirtualKey::Other(0x07));
+    }
+
+    #[test]
+    fn combines_surrogate_pairs() {
+        let mut combiner = SurrogatePairCombiner::new();
+        assert_eq!(combiner.push('a' as u16), Some('a'));
+
+        let mut units = [0u16; 2];
+        '\u{1F600}'.encode_utf16(&mut units);
+        assert_eq!(combiner.push(units[0]), None);
+        assert!(combiner.has_pending());
+        assert_eq!(combiner.push(units[1]), Some('\u{1F600}'));
+        assert!(!combiner.has_pending());
+    }
+
+    #[test]
+    fn replaces_unpaired_surrogates() {
+        let mut combiner = SurrogatePairCombiner::new();
+        assert_eq!(combiner.push(0xDC00), Some('\u{FFFD}'));
+        assert_eq!(combiner.push(0xD800), None);
+        assert_eq!(combiner.push('b' as u16), Some('b'));
+        assert!(!combiner.has_pending());
+    }
+}
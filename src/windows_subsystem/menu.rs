@@ -8,6 +8,7 @@ use crate::shared::strategy;
 use crate::shared::OkOrLastError;
 use crate::shared::{Error, Result};
 use crate::shared::{ManagedData, ManagedEntity, ManagedStrategy};
+use crate::windows_subsystem::ui_thread::{check_window_thread, UiThread};
 use crate::windows_subsystem::window::AnyWindow;
 
 #[derive(Clone)]
@@ -47,8 +48,10 @@ impl<T: ManagedStrategy> AnyWindow<T> {
         Ok(has_window_menu)
     }
 
+    /// The menu belongs to the window's thread, which must be this one.
     pub fn menu(&self) -> Result<Option<ForeignMenu>> {
         use winapi::um::winuser::GetMenu;
+        check_window_thread(self.data_ref().raw_handle())?;
         let menu = unsafe {
             let h = GetMenu(self.data_ref().raw_handle()).ok_or_last_error()?;
             h
@@ -65,6 +68,13 @@ impl<T: ManagedStrategy> AnyMenu<T> {
         Some(strategy::Foreign::attached_entity(MenuInner(h)))
     }
 
+    /// Like `new_from_attached`, for a menu used from the thread `ui` was
+    /// obtained on.
+    pub fn new_from_attached_checked(ui: &UiThread, h: HMENU) -> Result<Option<ForeignMenu>> {
+        ui.check()?;
+        Ok(Self::new_from_attached(h))
+    }
+
     pub fn item_by_command(&mut self, command: WORD) -> MenuItem {
         MenuItem {
             menu: self.data_mut(),
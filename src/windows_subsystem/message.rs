@@ -1,44 +1,277 @@
+use crate::shared::internal_error;
 use crate::shared::Error;
 use crate::shared::Result;
-use derive_more::From;
+use bitflags::bitflags;
+use derive_more::{From, Into};
 use std::mem::zeroed;
+use std::os::windows::io::AsRawHandle;
 use std::ptr::null_mut;
+use std::time::Duration;
 use winapi::shared::minwindef::LRESULT;
-use winapi::shared::minwindef::{BOOL, DWORD, UINT};
-use winapi::shared::windef::HWND;
-use winapi::um::winnt::LONG;
+use winapi::shared::minwindef::{BOOL, DWORD, UINT, WORD};
+use winapi::shared::windef::{HACCEL, HWND};
 use winapi::um::winuser::MSG;
 
+use crate::graphics_subsystem::Point;
 use crate::shared::booleanize;
+use crate::shared::CWideString;
+use crate::shared::ManagedStrategy;
+use crate::windows_subsystem::subclass::PreviousWindowProc;
+use crate::windows_subsystem::ui_thread::UiThread;
+use crate::windows_subsystem::user_message::{MessagePayload, UserMessage};
+use crate::windows_subsystem::window::{AnyWindow, ForeignWindow, WindowProcRequestArgs};
+use crate::windows_subsystem::wm::MessageRange;
 
 /// ECMA-234 Clause 6 CallWindowProc
-fn call_window_proc() {
-    unimplemented!()
+///
+/// # Safety
+///
+/// `args` is passed to the procedure as is, so any pointer it carries in
+/// `wparam` or `lparam` must be valid for that message.
+/// `PreviousWindowProc::forward` is the safe way to forward the message a
+/// subclass procedure is handling.
+pub unsafe fn call_window_proc<T: ManagedStrategy>(
+    window_proc: &PreviousWindowProc,
+    window: &AnyWindow<T>,
+    args: WindowProcRequestArgs,
+) -> MessageResult {
+    use winapi::um::winuser::CallWindowProcW;
+    CallWindowProcW(
+        window_proc.raw_proc(),
+        window.data_ref().raw_handle(),
+        args.msg,
+        args.wparam,
+        args.lparam,
+    )
+    .into()
+}
+
+pub(crate) fn check_pointer_free(args: &WindowProcRequestArgs) -> Result<()> {
+    use winapi::shared::winerror::ERROR_INVALID_PARAMETER;
+    if !args.is_pointer_free() {
+        return Err(Error::from_code(ERROR_INVALID_PARAMETER));
+    }
+    Ok(())
+}
+
+/// Registers a message identifier unique across the system for `name`.
+pub fn register_window_message(name: &str) -> Result<UINT> {
+    use winapi::um::winuser::RegisterWindowMessageW;
+    let name = CWideString::from(name);
+    unsafe {
+        let id = RegisterWindowMessageW(name.as_ptr());
+        if id == 0 {
+            return Error::last();
+        }
+        Ok(id)
+    }
+}
+
+impl<T: ManagedStrategy> AnyWindow<T> {
+    /// Calls the window procedure and waits for it to return.
+    ///
+    /// # Safety
+    ///
+    /// Whatever `wparam` and `lparam` point to must be valid for the way the
+    /// window procedure reads and writes it. `send_plain_message` is the safe
+    /// variant for messages that carry no pointers.
+    pub unsafe fn send_message(&self, args: WindowProcRequestArgs) -> MessageResult {
+        use winapi::um::winuser::SendMessageW;
+        SendMessageW(
+            self.data_ref().raw_handle(),
+            args.msg,
+            args.wparam,
+            args.lparam,
+        )
+        .into()
+    }
+
+    /// Places the message in the queue of the thread that owns the window.
+    ///
+    /// # Safety
+    ///
+    /// Whatever `wparam` and `lparam` point to must stay valid until the
+    /// message is handled. `post_plain_message` is the safe variant for
+    /// messages that carry no pointers.
+    pub unsafe fn post_message(&self, args: WindowProcRequestArgs) -> Result<()> {
+        use winapi::um::winuser::PostMessageW;
+        if !booleanize(PostMessageW(
+            self.data_ref().raw_handle(),
+            args.msg,
+            args.wparam,
+            args.lparam,
+        )) {
+            return Error::last();
+        }
+        Ok(())
+    }
+
+    /// Like `send_message`, but fails with `ERROR_INVALID_PARAMETER` unless
+    /// `args.is_pointer_free()`.
+    pub fn send_plain_message(&self, args: WindowProcRequestArgs) -> Result<MessageResult> {
+        check_pointer_free(&args)?;
+        Ok(unsafe { self.send_message(args) })
+    }
+
+    /// Like `post_message`, but fails with `ERROR_INVALID_PARAMETER` unless
+    /// `args.is_pointer_free()`.
+    pub fn post_plain_message(&self, args: WindowProcRequestArgs) -> Result<()> {
+        check_pointer_free(&args)?;
+        unsafe { self.post_message(args) }
+    }
+
+    pub fn send_user_message<P: MessagePayload>(
+        &self,
+        message: &UserMessage<P>,
+        payload: P,
+    ) -> MessageResult {
+        unsafe { self.send_message(message.encode(payload)) }
+    }
+
+    pub fn post_user_message<P: MessagePayload>(
+        &self,
+        message: &UserMessage<P>,
+        payload: P,
+    ) -> Result<()> {
+        unsafe { self.post_message(message.encode(payload)) }
+    }
 }
 
 #[derive(From)]
 pub struct Message(MSG);
 
-#[derive(From)]
+#[derive(From, Into)]
 pub struct MessageResult(LRESULT);
 
 impl Message {
+    /// A message that did not come from a queue, posted to the thread
+    /// rather than to a window. Mostly useful for tests.
+    pub fn synthetic(args: WindowProcRequestArgs) -> Self {
+        let mut msg: MSG = unsafe { zeroed() };
+        msg.message = args.msg;
+        msg.wParam = args.wparam;
+        msg.lParam = args.lparam;
+        Message(msg)
+    }
+
+    pub fn synthetic_for_window<T: ManagedStrategy>(
+        window: &AnyWindow<T>,
+        args: WindowProcRequestArgs,
+    ) -> Self {
+        let mut message = Self::synthetic(args);
+        message.0.hwnd = window.data_ref().raw_handle();
+        message
+    }
+
+    pub fn with_time(mut self, time: MessageTime) -> Self {
+        self.0.time = time.ticks();
+        self
+    }
+
+    pub fn with_point(mut self, point: Point) -> Self {
+        self.0.pt = point.into();
+        self
+    }
+
+    /// The target window, or `None` for thread messages.
+    pub fn window(&self) -> Option<ForeignWindow> {
+        ForeignWindow::new_from_attached(self.0.hwnd)
+    }
+
+    pub fn args(&self) -> WindowProcRequestArgs {
+        WindowProcRequestArgs {
+            msg: self.0.message,
+            wparam: self.0.wParam,
+            lparam: self.0.lParam,
+        }
+    }
+
+    /// The cursor position when the message was posted, in screen
+    /// coordinates.
+    pub fn point(&self) -> Point {
+        self.0.pt.into()
+    }
+
+    pub fn pos(&self) -> MessagePos {
+        MessagePos::from_point(self.point())
+    }
+
+    pub fn time(&self) -> MessageTime {
+        MessageTime::from_ticks(self.0.time)
+    }
+
     /// ECMA-234 Clause 7 DispatchMessage
     pub fn dispatch(self) -> MessageResult {
         use winapi::um::winuser::DispatchMessageW;
         unsafe { DispatchMessageW(&self.0).into() }
     }
+
+    /// Posts the WM_CHAR family for key messages. Returns whether it did.
+    pub fn translate(&self) -> bool {
+        use winapi::um::winuser::TranslateMessage;
+        unsafe { booleanize(TranslateMessage(&self.0)) }
+    }
+
+    /// Sends WM_COMMAND to `window` when the message is a key combination
+    /// in `table`. Returns whether it did; the message should not be
+    /// dispatched in that case.
+    pub fn translate_accelerator<T: ManagedStrategy>(
+        &self,
+        window: &AnyWindow<T>,
+        table: &AcceleratorTable,
+    ) -> bool {
+        use winapi::um::winuser::TranslateAcceleratorW;
+        unsafe {
+            TranslateAcceleratorW(
+                window.data_ref().raw_handle(),
+                table.0,
+                &self.0 as *const _ as _,
+            ) != 0
+        }
+    }
+
+    /// Handles keyboard navigation for the modeless `dialog`. Returns
+    /// whether the message was processed; it must not be dispatched again
+    /// in that case.
+    pub fn is_dialog_message<T: ManagedStrategy>(&self, dialog: &AnyWindow<T>) -> bool {
+        use winapi::um::winuser::IsDialogMessageW;
+        unsafe {
+            booleanize(IsDialogMessageW(
+                dialog.data_ref().raw_handle(),
+                &self.0 as *const _ as _,
+            ))
+        }
+    }
+}
+
+/// An accelerator table loaded from the executable's resources. Such
+/// tables are freed by the system when the process exits.
+pub struct AcceleratorTable(HACCEL);
+
+impl AcceleratorTable {
+    pub fn load_from_resource_id(id: WORD) -> Result<Self> {
+        use crate::shared::exe_instance;
+        use winapi::um::winuser::{LoadAcceleratorsW, MAKEINTRESOURCEW};
+        unsafe {
+            let h = LoadAcceleratorsW(exe_instance(), MAKEINTRESOURCEW(id));
+            if h.is_null() {
+                return Error::last();
+            }
+            Ok(AcceleratorTable(h))
+        }
+    }
 }
 
 pub enum QuitOrNormalMsg {
-    QuitMsg,
+    /// WM_QUIT, carrying the exit code passed to `request_quit`.
+    QuitMsg(i32),
     NormalMsg(Message),
 }
 
 impl QuitOrNormalMsg {
     pub fn not_quit(self) -> Option<Message> {
         match self {
-            QuitOrNormalMsg::QuitMsg => None,
+            QuitOrNormalMsg::QuitMsg(_) => None,
             QuitOrNormalMsg::NormalMsg(m) => Some(m),
         }
     }
@@ -78,17 +311,127 @@ impl MessageFilter {
     }
 }
 
-#[derive(From)]
-pub struct MessagePos(DWORD);
+bitflags! {
+    /// The PM_QS_* kinds of queued messages a `MessageFilter` retrieves.
+    /// Empty means all kinds.
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub struct QueueStatusKinds : UINT {
+        const INPUT = ::winapi::um::winuser::PM_QS_INPUT;
+        const PAINT = ::winapi::um::winuser::PM_QS_PAINT;
+        /// Posted messages, which like `PM_QS_POSTMESSAGE` includes
+        /// `HOTKEY` and `TIMER`.
+        const POSTED = ::winapi::um::winuser::PM_QS_POSTMESSAGE;
+        const HOTKEY = ::winapi::um::winuser::QS_HOTKEY << 16;
+        const TIMER = ::winapi::um::winuser::QS_TIMER << 16;
+        const SENT = ::winapi::um::winuser::PM_QS_SENDMESSAGE;
+    }
+}
 
-#[derive(From)]
-pub struct MessageTime(LONG);
+pub struct MessageFilterBuilder {
+    hwnd: HWND,
+    range: MessageRange,
+    kinds: QueueStatusKinds,
+}
 
-pub struct ForeignMessageLoop;
+impl Default for MessageFilterBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MessageFilterBuilder {
+    pub fn new() -> Self {
+        MessageFilterBuilder {
+            hwnd: null_mut(),
+            range: MessageRange::ALL,
+            kinds: QueueStatusKinds::empty(),
+        }
+    }
+
+    /// Only messages for `window` and its children.
+    pub fn window<T: ManagedStrategy>(mut self, window: &AnyWindow<T>) -> Self {
+        self.hwnd = window.data_ref().raw_handle();
+        self
+    }
+
+    /// Only messages posted to the thread, with no target window.
+    pub fn thread_messages_only(mut self) -> Self {
+        self.hwnd = -1isize as HWND;
+        self
+    }
+
+    pub fn range(mut self, range: MessageRange) -> Self {
+        self.range = range;
+        self
+    }
+
+    pub fn kinds(mut self, kinds: QueueStatusKinds) -> Self {
+        self.kinds = kinds;
+        self
+    }
+
+    pub fn create(self) -> MessageFilter {
+        MessageFilter {
+            hwnd: self.hwnd,
+            min: self.range.min,
+            max: self.range.max,
+            kinds: self.kinds.bits(),
+        }
+    }
+
+    /// GetMessage has no queue-status kinds, so they are ignored here.
+    pub fn create_simple(self) -> MessageSimpleFilter {
+        MessageSimpleFilter {
+            hwnd: self.hwnd,
+            min: self.range.min,
+            max: self.range.max,
+        }
+    }
+}
+
+pub use crate::windows_subsystem::window_message::{MessagePos, MessageTime};
+
+/// Identifies a thread, for posting messages to its queue.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ThreadId(DWORD);
+
+impl ThreadId {
+    pub(crate) fn from_raw(id: DWORD) -> Self {
+        ThreadId(id)
+    }
+
+    pub(crate) fn current() -> Self {
+        use winapi::um::processthreadsapi::GetCurrentThreadId;
+        ThreadId(unsafe { GetCurrentThreadId() })
+    }
+}
+
+/// How `ForeignMessageLoop::wait_for_next_incoming_or_handles` returned.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum WaitOutcome {
+    /// The handle at this index was signalled.
+    Signalled(usize),
+    /// The mutex at this index was abandoned by the thread that owned it.
+    Abandoned(usize),
+    MessageAvailable,
+    TimedOut,
+}
+
+/// The message loop of the current thread. It stays on that thread.
+pub struct ForeignMessageLoop {
+    ui_thread: UiThread,
+}
 
 impl ForeignMessageLoop {
     pub fn for_current_thread() -> Self {
-        ForeignMessageLoop
+        ForeignMessageLoop {
+            ui_thread: UiThread::current(),
+        }
+    }
+
+    /// The token needed to create windows on this thread.
+    pub fn ui_thread(&self) -> UiThread {
+        self.ui_thread
     }
 
     /// ECMA-234 Clause 8 GetMessage
@@ -109,7 +452,7 @@ impl ForeignMessageLoop {
             if ret == -1 {
                 Error::last()
             } else if ret == 0 {
-                Ok(QuitOrNormalMsg::QuitMsg)
+                Ok(QuitOrNormalMsg::QuitMsg(msg.wParam as i32))
             } else {
                 Ok(QuitOrNormalMsg::NormalMsg(msg.into()))
             }
@@ -178,6 +521,55 @@ impl ForeignMessageLoop {
         }
     }
 
+    /// Blocks until one of `handles` is signalled, new input arrives in
+    /// the queue or `timeout` elapses. Input already in the queue when this
+    /// is called also ends the wait. At most 63 handles can be waited on.
+    pub fn wait_for_next_incoming_or_handles<H: AsRawHandle>(
+        &mut self,
+        handles: &[H],
+        timeout: Option<Duration>,
+    ) -> Result<WaitOutcome> {
+        use winapi::shared::winerror::{ERROR_INVALID_PARAMETER, WAIT_TIMEOUT};
+        use winapi::um::winbase::{INFINITE, WAIT_ABANDONED_0, WAIT_FAILED, WAIT_OBJECT_0};
+        use winapi::um::winnt::{HANDLE, MAXIMUM_WAIT_OBJECTS};
+        use winapi::um::winuser::MsgWaitForMultipleObjectsEx;
+        use winapi::um::winuser::{MWMO_INPUTAVAILABLE, QS_ALLINPUT};
+
+        if handles.len() >= MAXIMUM_WAIT_OBJECTS as usize {
+            return Err(Error::from_code(ERROR_INVALID_PARAMETER));
+        }
+        let raw_handles: Vec<HANDLE> = handles
+            .iter()
+            .map(|h| h.as_raw_handle() as HANDLE)
+            .collect();
+        let count = raw_handles.len() as DWORD;
+        let millis = match timeout {
+            None => INFINITE,
+            Some(timeout) => timeout.as_millis().min(u128::from(INFINITE - 1)) as DWORD,
+        };
+        let r = unsafe {
+            MsgWaitForMultipleObjectsEx(
+                count,
+                raw_handles.as_ptr(),
+                millis,
+                QS_ALLINPUT,
+                MWMO_INPUTAVAILABLE,
+            )
+        };
+        match r {
+            WAIT_FAILED => Error::last(),
+            WAIT_TIMEOUT => Ok(WaitOutcome::TimedOut),
+            r if r < WAIT_OBJECT_0 + count => {
+                Ok(WaitOutcome::Signalled((r - WAIT_OBJECT_0) as usize))
+            }
+            r if r == WAIT_OBJECT_0 + count => Ok(WaitOutcome::MessageAvailable),
+            r if r >= WAIT_ABANDONED_0 && r < WAIT_ABANDONED_0 + count => {
+                Ok(WaitOutcome::Abandoned((r - WAIT_ABANDONED_0) as usize))
+            }
+            _ => internal_error(),
+        }
+    }
+
     /// ECMA-234 Clause 10 GetMessagePos
     pub fn last_message_pos(&mut self) -> MessagePos {
         use winapi::um::winuser::GetMessagePos;
@@ -190,10 +582,88 @@ impl ForeignMessageLoop {
         unsafe { GetMessageTime().into() }
     }
 
-    pub fn request_quit() {
+    pub fn thread_id(&self) -> ThreadId {
+        self.ui_thread.id()
+    }
+
+    /// Posts a message with no target window to the queue of `thread`.
+    ///
+    /// # Safety
+    ///
+    /// Whatever `wparam` and `lparam` point to must stay valid until the
+    /// message is handled.
+    pub unsafe fn post_thread_message(thread: ThreadId, args: WindowProcRequestArgs) -> Result<()> {
+        use winapi::um::winuser::PostThreadMessageW;
+        if !booleanize(PostThreadMessageW(
+            thread.0,
+            args.msg,
+            args.wparam,
+            args.lparam,
+        )) {
+            return Error::last();
+        }
+        Ok(())
+    }
+
+    /// Like `post_thread_message`, but fails with `ERROR_INVALID_PARAMETER`
+    /// unless `args.is_pointer_free()`.
+    pub fn post_plain_thread_message(thread: ThreadId, args: WindowProcRequestArgs) -> Result<()> {
+        check_pointer_free(&args)?;
+        unsafe { Self::post_thread_message(thread, args) }
+    }
+
+    pub fn post_thread_user_message<P: MessagePayload>(
+        thread: ThreadId,
+        message: &UserMessage<P>,
+        payload: P,
+    ) -> Result<()> {
+        unsafe { Self::post_thread_message(thread, message.encode(payload)) }
+    }
+
+    /// Runs the usual GetMessage, TranslateMessage and DispatchMessage loop
+    /// and returns the exit code from WM_QUIT.
+    pub fn run_until_quit(&mut self) -> Result<i32> {
+        self.run_until_quit_with(|_| false)
+    }
+
+    /// Like `run_until_quit`, but offers every message to `pre_translate`
+    /// first. Returning `true` marks the message as consumed, as
+    /// `translate_accelerator` and `is_dialog_message` do.
+    pub fn run_until_quit_with<F>(&mut self, mut pre_translate: F) -> Result<i32>
+    where
+        F: FnMut(&Message) -> bool,
+    {
+        loop {
+            match self.poll_wait()? {
+                QuitOrNormalMsg::QuitMsg(exit_code) => return Ok(exit_code),
+                QuitOrNormalMsg::NormalMsg(msg) => {
+                    if pre_translate(&msg) {
+                        continue;
+                    }
+                    msg.translate();
+                    msg.dispatch();
+                }
+            }
+        }
+    }
+
+    pub fn request_quit(exit_code: i32) {
         use winapi::um::winuser::PostQuitMessage;
         unsafe {
-            PostQuitMessage(0);
+            PostQuitMessage(exit_code);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn posted_kind_matches_pm_qs_postmessage() {
+        assert_eq!(QueueStatusKinds::POSTED.bits(), 0x0098_0000);
+        assert!(
+            QueueStatusKinds::POSTED.contains(QueueStatusKinds::HOTKEY | QueueStatusKinds::TIMER)
+        );
+    }
+}
@@ -1,18 +1,44 @@
+#[cfg(windows)]
 pub mod debug;
+pub mod keyboard;
+#[cfg(windows)]
 pub mod menu;
+#[cfg(windows)]
 pub mod message;
+#[cfg(windows)]
+pub mod subclass;
+pub mod timer;
+#[cfg(windows)]
+pub mod ui_thread;
+pub mod user_message;
+#[cfg(windows)]
+pub mod weak_window;
+#[cfg(windows)]
 pub mod window;
+pub mod window_message;
+pub mod window_route;
+#[cfg(windows)]
+pub mod window_state;
+#[cfg(windows)]
 pub mod window_graphics;
+#[cfg(windows)]
+pub mod window_handler;
+#[cfg(windows)]
 pub mod dialog;
+pub mod wm;
 
+#[cfg(windows)]
 use winapi::shared::minwindef::WORD;
+#[cfg(windows)]
 use crate::shared::CWideString;
 
+#[cfg(windows)]
 pub(crate) enum ResourceIDOrIDString {
     ID(WORD),
     String(CWideString),
 }
 
+#[cfg(windows)]
 impl ResourceIDOrIDString {
     pub(crate) fn as_ptr_or_int_ptr(&self) -> *const u16 {
         use winapi::um::winuser::MAKEINTRESOURCEW;
@@ -21,4 +47,4 @@ impl ResourceIDOrIDString {
             ResourceIDOrIDString::String(str) => str.as_ptr(),
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,308 @@
+use log::warn;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use winapi::shared::minwindef::{LPARAM, LRESULT, UINT, WPARAM};
+use winapi::shared::windef::HWND;
+use winapi::um::winuser::WNDPROC;
+
+use crate::shared::booleanize;
+use crate::shared::borrow_or_reentered;
+use crate::shared::CWideString;
+use crate::shared::ManagedStrategy;
+use crate::shared::{maybe_last_error, Error, Result};
+use crate::windows_subsystem::message::MessageResult;
+use crate::windows_subsystem::ui_thread::check_window_thread;
+use crate::windows_subsystem::weak_window::notify_destroyed;
+use crate::windows_subsystem::window::AnyWindow;
+use crate::windows_subsystem::window::WindowProcResponse;
+use crate::windows_subsystem::window::{WindowProcRequest, WindowProcRequestArgs};
+
+thread_local! {
+    static SUBCLASS_PROP: CWideString = CWideString::from("apiw.subclass");
+}
+
+fn subclass_prop() -> *const u16 {
+    SUBCLASS_PROP.with(CWideString::as_ptr)
+}
+
+/// The window procedure that was installed before a subclass, handed to
+/// the subclass procedure together with the message it is handling.
+pub struct PreviousWindowProc {
+    window_proc: WNDPROC,
+    hwnd: HWND,
+    args: WindowProcRequestArgs,
+}
+
+impl PreviousWindowProc {
+    pub(crate) fn raw_proc(&self) -> WNDPROC {
+        self.window_proc
+    }
+
+    /// Passes the message being handled to the previous procedure,
+    /// unchanged, and returns its result.
+    pub fn forward(&self) -> MessageResult {
+        use winapi::um::winuser::CallWindowProcW;
+        // The arguments are the ones the system passed for this very
+        // message, so whatever they point to is still valid.
+        unsafe {
+            CallWindowProcW(
+                self.window_proc,
+                self.hwnd,
+                self.args.msg,
+                self.args.wparam,
+                self.args.lparam,
+            )
+        }
+        .into()
+    }
+
+    /// Like `forward`, answering `request` with the result.
+    pub fn forward_request(&self, request: &mut WindowProcRequest) {
+        if let Some(response) = request.response.take() {
+            *response = WindowProcResponse::Done(self.forward().into());
+        } else {
+            warn!(target: "apiw", "Duplicate route for event: {}",
+                  "forward_request");
+        }
+    }
+}
+
+type SubclassProc = dyn FnMut(WindowProcRequest, &PreviousWindowProc);
+
+struct SubclassSlot {
+    previous: WNDPROC,
+    window_proc: RefCell<Option<Box<SubclassProc>>>,
+    /// Set once the guard is dropped while the subclass could not be
+    /// removed; messages then go straight to the previous procedure.
+    released: Cell<bool>,
+    detached: Cell<bool>,
+}
+
+impl SubclassSlot {
+    /// Puts the previous procedure back. When the window is not being
+    /// destroyed and another procedure has been installed on top of ours,
+    /// the trampoline stays in place so that chain keeps working, but the
+    /// subclass procedure is dropped and no longer called.
+    unsafe fn uninstall(&self, hwnd: HWND, window_destroyed: bool) {
+        use winapi::um::winuser::GWLP_WNDPROC;
+        use winapi::um::winuser::{GetWindowLongPtrW, RemovePropW, SetWindowLongPtrW};
+        if self.detached.get() {
+            return;
+        }
+        let current = GetWindowLongPtrW(hwnd, GWLP_WNDPROC);
+        if current == trampoline_address() {
+            SetWindowLongPtrW(
+                hwnd,
+                GWLP_WNDPROC,
+                self.previous.map_or(0, |f| f as usize) as _,
+            );
+        } else if !window_destroyed {
+            warn!(target: "apiw", "Window procedure replaced after subclassing, forwarding to the previous procedure until the window is destroyed");
+            self.release();
+            return;
+        }
+        self.detached.set(true);
+        let raw = RemovePropW(hwnd, subclass_prop());
+        if !raw.is_null() {
+            drop(Rc::from_raw(raw as *const SubclassSlot));
+        }
+    }
+
+    /// Stops calling the subclass procedure and drops it, or leaves that to
+    /// the trampoline if it is running.
+    fn release(&self) {
+        self.released.set(true);
+        let window_proc = match self.window_proc.try_borrow_mut() {
+            Ok(mut window_proc) => window_proc.take(),
+            Err(_) => None,
+        };
+        // Dropped outside the borrow, the closure may own other windows.
+        drop(window_proc);
+    }
+}
+
+unsafe extern "system" fn subclass_trampoline(
+    hwnd: HWND,
+    msg: UINT,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    use winapi::um::winuser::{CallWindowProcW, DefWindowProcW, GetPropW, WM_NCDESTROY};
+    let raw = GetPropW(hwnd, subclass_prop()) as *const SubclassSlot;
+    if raw.is_null() {
+        return DefWindowProcW(hwnd, msg, wparam, lparam);
+    }
+    // Keep the slot alive for this call even if the guard is dropped inside it.
+    Rc::increment_strong_count(raw);
+    let slot = Rc::from_raw(raw);
+
+    // A message sent while the procedure is running, such as one the
+    // previous procedure sends itself while handling a forwarded message,
+    // goes straight to the previous procedure, as does every message once
+    // the subclass is released.
+    let args = WindowProcRequestArgs {
+        msg,
+        wparam,
+        lparam,
+    };
+    let response = borrow_or_reentered(
+        &slot.window_proc,
+        |window_proc| {
+            let mut response = WindowProcResponse::Fallback;
+            if let Some(window_proc) = window_proc {
+                let request = WindowProcRequest {
+                    hwnd,
+                    args,
+                    response: Some(&mut response),
+                };
+                let previous = PreviousWindowProc {
+                    window_proc: slot.previous,
+                    hwnd,
+                    args,
+                };
+                (window_proc)(request, &previous);
+            }
+            response
+        },
+        || WindowProcResponse::Fallback,
+    );
+    if slot.released.get() {
+        // Released from inside the procedure.
+        slot.release();
+    }
+
+    let r = match response {
+        WindowProcResponse::Done(r) => r,
+        WindowProcResponse::Fallback => CallWindowProcW(slot.previous, hwnd, msg, wparam, lparam),
+    };
+    if msg == WM_NCDESTROY {
+        notify_destroyed(hwnd);
+        slot.uninstall(hwnd, true);
+    }
+    r
+}
+
+fn trampoline_address() -> isize {
+    subclass_trampoline as *const () as isize
+}
+
+/// Restores the previous window procedure when dropped. If another
+/// procedure has been installed on top since, the subclass procedure is
+/// dropped instead and messages pass through to the previous one.
+pub struct SubclassGuard {
+    hwnd: HWND,
+    slot: Rc<SubclassSlot>,
+}
+
+impl Drop for SubclassGuard {
+    fn drop(&mut self) {
+        unsafe {
+            self.slot.uninstall(self.hwnd, false);
+        }
+    }
+}
+
+impl<T: ManagedStrategy> AnyWindow<T> {
+    /// Installs `window_proc` in front of the window's current procedure.
+    /// Messages it leaves as `WindowProcResponse::Fallback` are forwarded to
+    /// the previous procedure. Only one subclass per window is supported.
+    pub fn subclass<F>(&self, window_proc: F) -> Result<SubclassGuard>
+    where
+        F: FnMut(WindowProcRequest, &PreviousWindowProc) + 'static,
+    {
+        use winapi::shared::winerror::ERROR_ALREADY_EXISTS;
+        use winapi::um::errhandlingapi::SetLastError;
+        use winapi::um::winuser::{GetPropW, RemovePropW, SetPropW};
+        use winapi::um::winuser::{GetWindowLongPtrW, SetWindowLongPtrW, GWLP_WNDPROC};
+        let hwnd = self.data_ref().raw_handle();
+        check_window_thread(hwnd)?;
+        unsafe {
+            if !GetPropW(hwnd, subclass_prop()).is_null() {
+                return Err(Error::from_code(ERROR_ALREADY_EXISTS));
+            }
+            SetLastError(0);
+            let mut previous = GetWindowLongPtrW(hwnd, GWLP_WNDPROC);
+            if previous == 0 {
+                previous = maybe_last_error(|| 0)?;
+            }
+            let slot = Rc::new(SubclassSlot {
+                previous: std::mem::transmute::<isize, WNDPROC>(previous),
+                window_proc: RefCell::new(Some(Box::new(window_proc))),
+                released: Cell::new(false),
+                detached: Cell::new(false),
+            });
+            let raw = Rc::into_raw(slot.clone());
+            if !booleanize(SetPropW(hwnd, subclass_prop(), raw as _)) {
+                drop(Rc::from_raw(raw));
+                return Error::last();
+            }
+            SetLastError(0);
+            if SetWindowLongPtrW(hwnd, GWLP_WNDPROC, trampoline_address()) == 0 {
+                if let Err(e) = maybe_last_error(|| ()) {
+                    RemovePropW(hwnd, subclass_prop());
+                    drop(Rc::from_raw(raw));
+                    return Err(e);
+                }
+            }
+            Ok(SubclassGuard { hwnd, slot })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicIsize, Ordering};
+    use winapi::um::winuser::{CallWindowProcW, SendMessageW, SetWindowLongPtrW, GWLP_WNDPROC};
+
+    use crate::windows_subsystem::ui_thread::UiThread;
+    use crate::windows_subsystem::window::{ForeignWindowClass, WindowBuilder};
+
+    static BELOW_OUTER: AtomicIsize = AtomicIsize::new(0);
+
+    unsafe extern "system" fn outer_proc(
+        hwnd: HWND,
+        msg: UINT,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        let below = std::mem::transmute::<isize, WNDPROC>(BELOW_OUTER.load(Ordering::SeqCst));
+        CallWindowProcW(below, hwnd, msg, wparam, lparam)
+    }
+
+    #[test]
+    fn released_subclass_under_another_procedure_is_skipped() {
+        use winapi::um::winuser::WM_USER;
+        let ui = UiThread::current();
+        let class = ForeignWindowClass::new_from_name("STATIC");
+        let window = WindowBuilder::new(&class).create(&ui).unwrap();
+        let hwnd = window.data_ref().raw_handle();
+
+        let calls = Rc::new(Cell::new(0));
+        let counted = calls.clone();
+        let guard = window
+            .subclass(move |mut request, previous| {
+                counted.set(counted.get() + 1);
+                previous.forward_request(&mut request);
+            })
+            .unwrap();
+        unsafe {
+            BELOW_OUTER.store(
+                SetWindowLongPtrW(hwnd, GWLP_WNDPROC, outer_proc as *const () as isize),
+                Ordering::SeqCst,
+            );
+            SendMessageW(hwnd, WM_USER, 0, 0);
+        }
+        assert_eq!(calls.get(), 1);
+
+        drop(guard);
+        // The closure is gone, but the chain through the trampoline works.
+        assert_eq!(Rc::strong_count(&calls), 1);
+        unsafe {
+            SendMessageW(hwnd, WM_USER, 0, 0);
+        }
+        assert_eq!(calls.get(), 1);
+
+        window.destroy().unwrap();
+    }
+}
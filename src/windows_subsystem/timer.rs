@@ -0,0 +1,253 @@
+use std::time::Duration;
+
+/// Smallest interval `SetTimer` accepts; shorter ones are raised to it.
+pub const USER_TIMER_MINIMUM: u32 = 0x0000_000A;
+/// Largest interval `SetTimer` accepts.
+pub const USER_TIMER_MAXIMUM: u32 = 0x7FFF_FFFF;
+
+/// Converts `interval` to the millisecond count `SetTimer` takes, rounding
+/// up partial milliseconds and clamping to the range the system accepts.
+pub fn timer_interval_millis(interval: Duration) -> u32 {
+    let millis = interval.as_nanos().div_ceil(1_000_000);
+    if millis > u128::from(USER_TIMER_MAXIMUM) {
+        USER_TIMER_MAXIMUM
+    } else if millis < u128::from(USER_TIMER_MINIMUM) {
+        USER_TIMER_MINIMUM
+    } else {
+        millis as u32
+    }
+}
+
+#[cfg(windows)]
+pub use self::registry::TimerGuard;
+#[cfg(windows)]
+pub(crate) use self::registry::{forget_window_timers, unregister_timer};
+
+#[cfg(windows)]
+mod registry {
+    use log::warn;
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
+    use std::num::NonZeroUsize;
+    use std::rc::Rc;
+    use std::time::Duration;
+    use winapi::shared::windef::HWND;
+
+    use crate::shared::ManagedStrategy;
+    use crate::windows_subsystem::weak_window::install_destroy_hook;
+    use crate::windows_subsystem::window::{AnyWindow, TimerProcRequest};
+    use crate::Result;
+
+    type TimerCallback = Rc<RefCell<Box<dyn FnMut(TimerProcRequest)>>>;
+
+    /// A registered callback, tagged so that a guard only ever removes the
+    /// timer it set and not one that replaced it.
+    struct TimerEntry {
+        generation: u64,
+        callback: TimerCallback,
+    }
+
+    thread_local! {
+        static TIMERS: RefCell<HashMap<(usize, usize), TimerEntry>> =
+            RefCell::new(HashMap::new());
+        static NEXT_GENERATION: Cell<u64> = const { Cell::new(0) };
+    }
+
+    fn registry_timer_proc(request: TimerProcRequest) {
+        let key = (request.hwnd as usize, request.id);
+        let callback = TIMERS.with(|timers| {
+            timers
+                .borrow()
+                .get(&key)
+                .map(|entry| entry.callback.clone())
+        });
+        let callback = match callback {
+            Some(callback) => callback,
+            None => return,
+        };
+        match callback.try_borrow_mut() {
+            Ok(mut callback) => (callback)(request),
+            Err(_) => {
+                warn!(target: "apiw", "Timer callback re-entered, skipping timer: {}", key.1);
+            }
+        };
+    }
+
+    pub(crate) fn unregister_timer(hwnd: HWND, id: usize) {
+        // Dropped outside the borrow, the closure may own other timers.
+        let callback = TIMERS.with(|timers| timers.borrow_mut().remove(&(hwnd as usize, id)));
+        drop(callback);
+    }
+
+    /// Drops the closures of every timer of a destroyed window, so they do
+    /// not outlive it and a recycled handle does not inherit them.
+    pub(crate) fn forget_window_timers(hwnd: HWND) {
+        let entries: Vec<TimerEntry> = TIMERS
+            .try_with(|timers| {
+                let mut timers = timers.borrow_mut();
+                let keys: Vec<_> = timers
+                    .keys()
+                    .filter(|key| key.0 == hwnd as usize)
+                    .cloned()
+                    .collect();
+                keys.iter().filter_map(|key| timers.remove(key)).collect()
+            })
+            .unwrap_or_default();
+        // Dropped outside the borrow, the closures may own other timers.
+        drop(entries);
+    }
+
+    /// Kills its timer and drops the closure when dropped, unless the timer
+    /// has been replaced or its window destroyed since.
+    #[must_use]
+    pub struct TimerGuard {
+        hwnd: HWND,
+        id: NonZeroUsize,
+        generation: u64,
+    }
+
+    impl TimerGuard {
+        pub fn id(&self) -> NonZeroUsize {
+            self.id
+        }
+    }
+
+    impl Drop for TimerGuard {
+        fn drop(&mut self) {
+            use winapi::um::winuser::KillTimer;
+            let key = (self.hwnd as usize, self.id.get());
+            // Dropped outside the borrow, the closure may own other timers.
+            let entry = TIMERS.with(|timers| {
+                let mut timers = timers.borrow_mut();
+                match timers.get(&key) {
+                    Some(entry) if entry.generation == self.generation => timers.remove(&key),
+                    _ => None,
+                }
+            });
+            if entry.is_some() {
+                unsafe {
+                    KillTimer(self.hwnd, self.id.get());
+                }
+            }
+            drop(entry);
+        }
+    }
+
+    impl<T: ManagedStrategy> AnyWindow<T> {
+        /// Calls `callback` on this thread every `interval` until the
+        /// returned guard is dropped. Setting a timer with an `id` already in
+        /// use on this window replaces it.
+        pub fn set_timer<F>(
+            &self,
+            id: NonZeroUsize,
+            interval: Duration,
+            callback: F,
+        ) -> Result<TimerGuard>
+        where
+            F: FnMut(TimerProcRequest) + 'static,
+        {
+            let hwnd = self.data_ref().raw_handle();
+            let key = (hwnd as usize, id.get());
+            let generation = NEXT_GENERATION.with(|next| {
+                let generation = next.get();
+                next.set(generation.wrapping_add(1));
+                generation
+            });
+            let entry = TimerEntry {
+                generation,
+                callback: Rc::new(RefCell::new(Box::new(callback))),
+            };
+            // So the closure is dropped with the window.
+            install_destroy_hook()?;
+            let previous = TIMERS.with(|timers| timers.borrow_mut().insert(key, entry));
+            if let Err(e) =
+                self.set_timer_with_id(id, interval, crate::timer_proc!(registry_timer_proc))
+            {
+                // The previous timer, if any, is still set.
+                let failed = TIMERS.with(|timers| {
+                    let mut timers = timers.borrow_mut();
+                    match previous {
+                        Some(previous) => timers.insert(key, previous),
+                        None => timers.remove(&key),
+                    }
+                });
+                drop(failed);
+                return Err(e);
+            }
+            drop(previous);
+            Ok(TimerGuard {
+                hwnd,
+                id,
+                generation,
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::time::Duration;
+
+        use crate::windows_subsystem::ui_thread::UiThread;
+        use crate::windows_subsystem::window::{ForeignWindowClass, WindowBuilder};
+
+        fn timer_count() -> usize {
+            TIMERS.with(|timers| timers.borrow().len())
+        }
+
+        #[test]
+        fn destroying_the_window_drops_its_timers() {
+            let ui = UiThread::current();
+            let class = ForeignWindowClass::new_from_name("STATIC");
+            let window = WindowBuilder::new(&class).create(&ui).unwrap();
+            let captured = Rc::new(());
+            let owned = captured.clone();
+            let id = NonZeroUsize::new(1).unwrap();
+            let guard = window
+                .set_timer(id, Duration::from_secs(60), move |_| {
+                    let _ = &owned;
+                })
+                .unwrap();
+            assert_eq!(timer_count(), 1);
+
+            window.destroy().unwrap();
+            assert_eq!(timer_count(), 0);
+            assert_eq!(Rc::strong_count(&captured), 1);
+            // Finds nothing to kill on the stale handle.
+            drop(guard);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_intervals_to_milliseconds() {
+        assert_eq!(timer_interval_millis(Duration::from_secs(1)), 1000);
+        assert_eq!(timer_interval_millis(Duration::from_millis(2500)), 2500);
+        assert_eq!(timer_interval_millis(Duration::from_secs(90)), 90_000);
+        assert_eq!(timer_interval_millis(Duration::from_micros(16_400)), 17);
+    }
+
+    #[test]
+    fn clamps_timer_intervals() {
+        assert_eq!(
+            timer_interval_millis(Duration::from_secs(0)),
+            USER_TIMER_MINIMUM
+        );
+        assert_eq!(
+            timer_interval_millis(Duration::from_millis(3)),
+            USER_TIMER_MINIMUM
+        );
+        assert_eq!(
+            timer_interval_millis(Duration::from_secs(u64::MAX)),
+            USER_TIMER_MAXIMUM
+        );
+        assert_eq!(
+            timer_interval_millis(Duration::from_millis(u64::from(USER_TIMER_MAXIMUM))),
+            USER_TIMER_MAXIMUM
+        );
+    }
+}
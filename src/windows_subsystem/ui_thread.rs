@@ -0,0 +1,118 @@
+//! Thread affinity of USER objects.
+//!
+//! Windows, menus and device contexts belong to the thread that created
+//! them; their wrappers hold raw handles and so are neither `Send` nor
+//! `Sync`. Creating them takes a `UiThread` token, which only a
+//! `ForeignMessageLoop` hands out and which cannot leave its thread. Other
+//! threads talk to a window through a `WindowSender`, which can only post.
+//! Operations that only work on the owning thread additionally check it in
+//! debug builds.
+
+use std::marker::PhantomData;
+use winapi::shared::windef::HWND;
+
+use crate::shared::booleanize;
+use crate::shared::ManagedStrategy;
+use crate::shared::{Error, Result};
+use crate::windows_subsystem::message::{check_pointer_free, ThreadId};
+use crate::windows_subsystem::user_message::{MessagePayload, UserMessage};
+use crate::windows_subsystem::window::{AnyWindow, WindowProcRequestArgs};
+
+/// Proof that the code holding it runs on a thread with a message loop.
+#[derive(Copy, Clone, Debug)]
+pub struct UiThread {
+    id: ThreadId,
+    not_send: PhantomData<*mut ()>,
+}
+
+impl UiThread {
+    pub(crate) fn current() -> Self {
+        UiThread {
+            id: ThreadId::current(),
+            not_send: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> ThreadId {
+        self.id
+    }
+
+    /// In debug builds, fails with `ERROR_INVALID_THREAD_ID` unless called
+    /// on the thread the token was obtained on.
+    pub(crate) fn check(&self) -> Result<()> {
+        use winapi::shared::winerror::ERROR_INVALID_THREAD_ID;
+        if cfg!(debug_assertions) && self.id != ThreadId::current() {
+            return Err(Error::from_code(ERROR_INVALID_THREAD_ID));
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn window_thread(hwnd: HWND) -> ThreadId {
+    use std::ptr::null_mut;
+    use winapi::um::winuser::GetWindowThreadProcessId;
+    ThreadId::from_raw(unsafe { GetWindowThreadProcessId(hwnd, null_mut()) })
+}
+
+/// In debug builds, fails with `ERROR_INVALID_THREAD_ID` unless `hwnd`
+/// belongs to the calling thread.
+pub(crate) fn check_window_thread(hwnd: HWND) -> Result<()> {
+    use winapi::shared::winerror::ERROR_INVALID_THREAD_ID;
+    if cfg!(debug_assertions) && window_thread(hwnd) != ThreadId::current() {
+        return Err(Error::from_code(ERROR_INVALID_THREAD_ID));
+    }
+    Ok(())
+}
+
+/// A window handle other threads may use to post messages to the window.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct WindowSender {
+    hwnd: usize,
+}
+
+impl WindowSender {
+    /// # Safety
+    ///
+    /// Whatever `wparam` and `lparam` point to must stay valid until the
+    /// message is handled.
+    pub unsafe fn post_message(&self, args: WindowProcRequestArgs) -> Result<()> {
+        use winapi::um::winuser::PostMessageW;
+        if !booleanize(PostMessageW(
+            self.hwnd as HWND,
+            args.msg,
+            args.wparam,
+            args.lparam,
+        )) {
+            return Error::last();
+        }
+        Ok(())
+    }
+
+    /// Like `post_message`, but fails with `ERROR_INVALID_PARAMETER` unless
+    /// `args.is_pointer_free()`.
+    pub fn post_plain_message(&self, args: WindowProcRequestArgs) -> Result<()> {
+        check_pointer_free(&args)?;
+        unsafe { self.post_message(args) }
+    }
+
+    pub fn post_user_message<P: MessagePayload>(
+        &self,
+        message: &UserMessage<P>,
+        payload: P,
+    ) -> Result<()> {
+        unsafe { self.post_message(message.encode(payload)) }
+    }
+
+    /// The thread that owns the window.
+    pub fn thread_id(&self) -> ThreadId {
+        window_thread(self.hwnd as HWND)
+    }
+}
+
+impl<T: ManagedStrategy> AnyWindow<T> {
+    pub fn sender(&self) -> WindowSender {
+        WindowSender {
+            hwnd: self.data_ref().raw_handle() as usize,
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::marker::PhantomData;
+use std::mem::{size_of, MaybeUninit};
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::windows_subsystem::window_message::WindowProcRequestArgs;
+use crate::windows_subsystem::wm;
+
+/// Last identifier of the WM_APP range.
+const WM_APP_LAST: u32 = 0xBFFF;
+
+static NEXT_APP_MESSAGE: AtomicU32 = AtomicU32::new(wm::WM_APP);
+
+/// A value that can be bit-copied into `wparam` and `lparam` and back.
+///
+/// # Safety
+///
+/// The type must contain no padding bytes, and every bit pattern of its
+/// size must be a valid value. That rules out `bool`, `char`, enums,
+/// references and pointers.
+pub unsafe trait MessagePayload: Copy + Send + 'static {}
+
+macro_rules! message_payloads {
+    ($($ty:ty),*) => {
+        $(unsafe impl MessagePayload for $ty {})*
+    };
+}
+
+message_payloads!(
+    (),
+    u8,
+    u16,
+    u32,
+    u64,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    isize,
+    f32,
+    f64
+);
+
+unsafe impl<T: MessagePayload, const N: usize> MessagePayload for [T; N] {}
+
+/// A custom message identifier carrying a `P` bit-copied into
+/// `wparam` and `lparam`. `P` may be at most two pointers in size.
+pub struct UserMessage<P> {
+    id: u32,
+    payload: PhantomData<fn(P) -> P>,
+}
+
+impl<P> Clone for UserMessage<P> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<P> Copy for UserMessage<P> {}
+
+impl<P> std::fmt::Debug for UserMessage<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("UserMessage").field(&self.id).finish()
+    }
+}
+
+impl<P: MessagePayload> UserMessage<P> {
+    const PAYLOAD_FITS: () = assert!(
+        size_of::<P>() <= 2 * size_of::<usize>(),
+        "UserMessage payload does not fit in wparam and lparam"
+    );
+
+    /// Allocates the next unused identifier in the WM_APP range, or `None`
+    /// once the range is used up.
+    pub fn allocate() -> Option<Self> {
+        NEXT_APP_MESSAGE
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |id| {
+                if id <= WM_APP_LAST {
+                    Some(id + 1)
+                } else {
+                    None
+                }
+            })
+            .ok()
+            .map(Self::from_id)
+    }
+
+    /// Every message with this identifier the window procedure receives is
+    /// decoded as a `P`, whoever sent it.
+    pub fn from_id(id: u32) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::PAYLOAD_FITS;
+        UserMessage {
+            id,
+            payload: PhantomData,
+        }
+    }
+
+    /// Uses the identifier `RegisterWindowMessageW` returns for `name`.
+    /// Any process can send a registered message.
+    #[cfg(windows)]
+    pub fn registered(name: &str) -> crate::Result<Self> {
+        use crate::windows_subsystem::message::register_window_message;
+        Ok(Self::from_id(register_window_message(name)?))
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn encode(&self, payload: P) -> WindowProcRequestArgs {
+        let mut words = [0usize; 2];
+        unsafe {
+            ptr::copy_nonoverlapping(
+                &payload as *const P as *const u8,
+                words.as_mut_ptr() as *mut u8,
+                size_of::<P>(),
+            );
+        }
+        WindowProcRequestArgs {
+            msg: self.id,
+            wparam: words[0],
+            lparam: words[1] as isize,
+        }
+    }
+
+    pub fn decode(&self, args: &WindowProcRequestArgs) -> Option<P> {
+        if args.msg != self.id {
+            return None;
+        }
+        let words = [args.wparam, args.lparam as usize];
+        let mut payload = MaybeUninit::<P>::uninit();
+        unsafe {
+            ptr::copy_nonoverlapping(
+                words.as_ptr() as *const u8,
+                payload.as_mut_ptr() as *mut u8,
+                size_of::<P>(),
+            );
+            Some(payload.assume_init())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, PartialEq, Debug)]
+    #[repr(C)]
+    struct Progress {
+        done: u16,
+        total: u16,
+        ratio: f32,
+    }
+
+    unsafe impl MessagePayload for Progress {}
+
+    #[test]
+    fn allocates_distinct_app_messages() {
+        let a = UserMessage::<u32>::allocate().unwrap();
+        let b = UserMessage::<u32>::allocate().unwrap();
+        assert_ne!(a.id(), b.id());
+        for id in [a.id(), b.id()].iter() {
+            assert!(*id >= wm::WM_APP && *id <= WM_APP_LAST);
+        }
+    }
+
+    #[test]
+    fn round_trips_payloads() {
+        let unit = UserMessage::<()>::allocate().unwrap();
+        assert_eq!(unit.decode(&unit.encode(())), Some(()));
+
+        let pair = UserMessage::<[i32; 2]>::allocate().unwrap();
+        assert_eq!(
+            pair.decode(&pair.encode([-7, 1 << 20])),
+            Some([-7, 1 << 20])
+        );
+
+        let progress = UserMessage::<Progress>::allocate().unwrap();
+        let value = Progress {
+            done: 3,
+            total: 9,
+            ratio: 0.5,
+        };
+        let args = progress.encode(value);
+        assert_eq!(args.msg, progress.id());
+        assert_eq!(progress.decode(&args), Some(value));
+    }
+
+    #[test]
+    fn ignores_other_messages() {
+        let message = UserMessage::<u8>::allocate().unwrap();
+        let args = WindowProcRequestArgs {
+            msg: wm::WM_PAINT,
+            wparam: 1,
+            lparam: 0,
+        };
+        assert_eq!(message.decode(&args), None);
+    }
+}
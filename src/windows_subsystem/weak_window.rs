@@ -0,0 +1,158 @@
+//! Window handles that notice when their window is destroyed.
+//!
+//! Liveness is tracked for the windows of the current thread and cleared on
+//! `WM_NCDESTROY` by a `WH_CALLWNDPROC` hook, whichever procedure the
+//! window has. For windows of other threads only `IsWindow` is checked,
+//! which cannot tell a recycled handle apart.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use winapi::ctypes::c_int;
+use winapi::shared::minwindef::{LPARAM, LRESULT, WPARAM};
+use winapi::shared::windef::{HHOOK, HWND};
+
+use crate::shared::booleanize;
+use crate::shared::ManagedStrategy;
+use crate::shared::{Error, Result};
+use crate::windows_subsystem::message::ThreadId;
+use crate::windows_subsystem::timer::forget_window_timers;
+use crate::windows_subsystem::ui_thread::window_thread;
+use crate::windows_subsystem::window::{AnyWindow, ForeignWindow};
+use crate::windows_subsystem::window_route::forget_char_combiner;
+
+/// Unhooked when the thread exits.
+struct DestroyHook(HHOOK);
+
+impl Drop for DestroyHook {
+    fn drop(&mut self) {
+        use winapi::um::winuser::UnhookWindowsHookEx;
+        unsafe {
+            UnhookWindowsHookEx(self.0);
+        }
+    }
+}
+
+thread_local! {
+    static LIVE_WINDOWS: RefCell<HashMap<usize, Rc<Cell<bool>>>> = RefCell::new(HashMap::new());
+    static DESTROY_HOOK: RefCell<Option<DestroyHook>> = const { RefCell::new(None) };
+}
+
+unsafe extern "system" fn destroy_hook_proc(
+    code: c_int,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    use std::ptr::null_mut;
+    use winapi::um::winuser::{CallNextHookEx, CWPSTRUCT, HC_ACTION, WM_NCDESTROY};
+    if code == HC_ACTION {
+        let message = &*(lparam as *const CWPSTRUCT);
+        if message.message == WM_NCDESTROY {
+            notify_destroyed(message.hwnd);
+        }
+    }
+    CallNextHookEx(null_mut(), code, wparam, lparam)
+}
+
+/// Makes sure `notify_destroyed` runs for every window of this thread.
+pub(crate) fn install_destroy_hook() -> Result<()> {
+    use std::ptr::null_mut;
+    use winapi::um::processthreadsapi::GetCurrentThreadId;
+    use winapi::um::winuser::{SetWindowsHookExW, WH_CALLWNDPROC};
+    DESTROY_HOOK.with(|hook| {
+        let mut hook = hook.borrow_mut();
+        if hook.is_none() {
+            let h = unsafe {
+                SetWindowsHookExW(
+                    WH_CALLWNDPROC,
+                    Some(destroy_hook_proc),
+                    null_mut(),
+                    GetCurrentThreadId(),
+                )
+            };
+            if h.is_null() {
+                return Error::last();
+            }
+            *hook = Some(DestroyHook(h));
+        }
+        Ok(())
+    })
+}
+
+/// Starts tracking a window of the current thread, or returns the token it
+/// already has.
+pub(crate) fn track_window(hwnd: HWND) -> Result<Rc<Cell<bool>>> {
+    use winapi::shared::winerror::ERROR_INVALID_THREAD_ID;
+    if window_thread(hwnd) != ThreadId::current() {
+        return Err(Error::from_code(ERROR_INVALID_THREAD_ID));
+    }
+    install_destroy_hook()?;
+    Ok(LIVE_WINDOWS.with(|windows| {
+        windows
+            .borrow_mut()
+            .entry(hwnd as usize)
+            .or_insert_with(|| Rc::new(Cell::new(true)))
+            .clone()
+    }))
+}
+
+/// Like `track_window`, but a window that cannot be tracked gets a token
+/// that is never cleared, leaving only the `IsWindow` check.
+pub(crate) fn liveness_token(hwnd: HWND) -> Rc<Cell<bool>> {
+    track_window(hwnd).unwrap_or_else(|_| Rc::new(Cell::new(true)))
+}
+
+/// Whether `hwnd` has a liveness token that has not been cleared yet.
+pub(crate) fn is_tracked(hwnd: HWND) -> bool {
+    LIVE_WINDOWS.with(|windows| windows.borrow().contains_key(&(hwnd as usize)))
+}
+
+/// Called on `WM_NCDESTROY`, by the destroy hook and by generated window
+/// procedures. Also forgets the per-window state kept by this crate.
+#[doc(hidden)]
+pub fn notify_destroyed(hwnd: HWND) {
+    // Windows can still be destroyed while thread-local storage is torn down.
+    let token = LIVE_WINDOWS
+        .try_with(|windows| windows.borrow_mut().remove(&(hwnd as usize)))
+        .ok()
+        .flatten();
+    if let Some(token) = token {
+        token.set(false);
+    }
+    forget_char_combiner(hwnd);
+    forget_window_timers(hwnd);
+}
+
+/// A window handle that refuses to be used after the window is destroyed.
+#[derive(Clone)]
+pub struct WeakWindow {
+    hwnd: HWND,
+    alive: Rc<Cell<bool>>,
+}
+
+impl WeakWindow {
+    pub fn is_alive(&self) -> bool {
+        use winapi::um::winuser::IsWindow;
+        self.alive.get() && unsafe { booleanize(IsWindow(self.hwnd)) }
+    }
+
+    /// Fails with `ERROR_INVALID_WINDOW_HANDLE` once the window is gone.
+    pub fn upgrade(&self) -> Result<ForeignWindow> {
+        use winapi::shared::winerror::ERROR_INVALID_WINDOW_HANDLE;
+        if !self.is_alive() {
+            return Err(Error::from_code(ERROR_INVALID_WINDOW_HANDLE));
+        }
+        ForeignWindow::new_from_attached(self.hwnd)
+            .ok_or_else(|| Error::from_code(ERROR_INVALID_WINDOW_HANDLE))
+    }
+}
+
+impl<T: ManagedStrategy> AnyWindow<T> {
+    pub fn downgrade(&self) -> WeakWindow {
+        let hwnd = self.data_ref().raw_handle();
+        WeakWindow {
+            hwnd,
+            alive: liveness_token(hwnd),
+        }
+    }
+}
@@ -22,12 +22,20 @@ use crate::shared::CWideString;
 use crate::shared::ManagedStrategy;
 //use utils::{Handle, Managed, Temporary};
 //use utils::System;
+use crate::graphics_subsystem::Point;
 use crate::graphics_subsystem::Rect;
 use crate::graphics_subsystem::Size;
 use crate::shared::strategy;
 use crate::shared::ManagedData;
 use crate::shared::ManagedEntity;
 use crate::shared::OkOrLastError;
+use crate::windows_subsystem::menu::ForeignMenu;
+use crate::windows_subsystem::ui_thread::{check_window_thread, UiThread};
+use crate::windows_subsystem::weak_window::{is_tracked, track_window};
+use crate::windows_subsystem::window_handler::{handler_window_proc, WindowHandler};
+use crate::windows_subsystem::window_state::{defer_destroy, with_pending_handoff, WindowStateHandoff};
+use std::any::Any;
+use std::sync::Mutex;
 
 pub type AnyWindowClass<T> = ManagedEntity<WindowClassInner, T>;
 pub type ForeignWindowClass = AnyWindowClass<strategy::Foreign>;
@@ -58,15 +66,27 @@ impl ManagedData for WindowClassInner {
             let succeeded = booleanize(UnregisterClassW(self.as_ptr_or_atom_ptr(), exe_instance()));
             if !succeeded {
                 warn!(target: "apiw", "Failed to cleanup {}, last error: {:?}", "WindowClass", Error::last::<()>());
+            } else if let WindowClassInner::Atom(atom) = self {
+                HANDLER_CLASSES.lock().unwrap().retain(|a| a != atom);
             }
         }
     }
 }
 
+/// Atoms of the classes registered with `WindowClassBuilder::handler`, whose
+/// procedure takes the window's `lpCreateParams` for itself.
+static HANDLER_CLASSES: Mutex<Vec<ATOM>> = Mutex::new(Vec::new());
+
 impl ForeignWindowClass {
     fn new_with_atom(v: ATOM) -> ForeignWindowClass {
         strategy::Foreign::attached_entity(WindowClassInner::Atom(v))
     }
+
+    /// Refers to an already registered class, such as the system "EDIT" or
+    /// "BUTTON" classes.
+    pub fn new_from_name(name: &str) -> ForeignWindowClass {
+        strategy::Foreign::attached_entity(WindowClassInner::String(name.into()))
+    }
 }
 
 impl<T: ManagedStrategy> ManagedEntity<WindowClassInner, T> {
@@ -170,6 +190,7 @@ pub struct WindowClassBuilder {
     cursor: Option<HCURSOR>,
     menu: Option<ResourceIDOrIDString>,
     window_proc: WNDPROC,
+    handler: bool,
 }
 
 type WndProcInner = unsafe extern "system" fn(_: HWND, _: UINT, _: WPARAM, _: LPARAM) -> LRESULT;
@@ -187,11 +208,21 @@ impl WindowClassBuilder {
             cursor: None,
             menu: None,
             window_proc: Some(::winapi::um::winuser::DefWindowProcW),
+            handler: false,
         }
     }
 
     pub fn window_proc(mut self, wnd_proc: WndProcInner) -> Self {
         self.window_proc = Some(wnd_proc);
+        self.handler = false;
+        self
+    }
+
+    /// Uses a window procedure that dispatches to the `H` attached to each
+    /// window with `WindowBuilder::handler`.
+    pub fn handler<H: WindowHandler>(mut self) -> Self {
+        self.window_proc = Some(handler_window_proc::<H>);
+        self.handler = true;
         self
     }
 
@@ -259,6 +290,9 @@ impl WindowClassBuilder {
             }
             h
         };
+        if self.handler {
+            HANDLER_CLASSES.lock().unwrap().push(window_class);
+        }
         Ok(ForeignWindowClass::new_with_atom(window_class))
     }
 }
@@ -299,6 +333,8 @@ impl MenuOrChildWindowId {
 
 pub type AnyWindow<T> = ManagedEntity<WindowInner, T>;
 pub type ForeignWindow = AnyWindow<strategy::Foreign>;
+/// A window destroyed when dropped, unless it has been destroyed already.
+pub type OwnedWindow = AnyWindow<strategy::Local<'static>>;
 
 #[derive(Clone)]
 pub struct WindowInner(HWND);
@@ -315,6 +351,10 @@ impl ManagedData for WindowInner {
     }
     fn delete(&mut self) {
         use winapi::um::winuser::DestroyWindow;
+        if !is_tracked(self.raw_handle()) {
+            // Already destroyed; the handle may have been reused.
+            return;
+        }
         unsafe {
             let succeeded = booleanize(DestroyWindow(self.raw_handle()));
             if !succeeded {
@@ -334,6 +374,8 @@ pub struct WindowBuilder<'a, 'b> {
     position: Option<(c_int, c_int)>,
     size: Option<(c_int, c_int)>,
     param: LPVOID,
+    state: Option<Box<dyn Any>>,
+    message_only: bool,
 }
 
 impl<'a, 'b> WindowBuilder<'a, 'b> {
@@ -348,6 +390,8 @@ impl<'a, 'b> WindowBuilder<'a, 'b> {
             position: None,
             size: None,
             param: 0usize as _,
+            state: None,
+            message_only: false,
         }
     }
 
@@ -361,14 +405,95 @@ impl<'a, 'b> WindowBuilder<'a, 'b> {
         self
     }
 
+    pub fn extended_style(mut self, extended_style: WindowExtendedStyles) -> Self {
+        self.style.1 = extended_style.bits();
+        self
+    }
+
+    /// The owner of a top-level window, or the parent of a child window.
+    pub fn parent<T: ManagedStrategy>(mut self, parent: &'b AnyWindow<T>) -> Self {
+        self.parent = Some(parent.data_ref());
+        self
+    }
+
+    /// The menu bar of a top-level window. It is destroyed along with the
+    /// window.
+    pub fn menu(mut self, menu: &ForeignMenu) -> Self {
+        self.menu = Some(MenuOrChildWindowId::Menu(menu.data_ref().raw_handle()));
+        self
+    }
+
+    /// The identifier a child window reports in notifications to its parent.
+    pub fn child_id(mut self, id: WORD) -> Self {
+        self.menu = Some(MenuOrChildWindowId::ChildWindowId(id));
+        self
+    }
+
+    /// In screen coordinates for top-level windows, and in the parent's
+    /// client coordinates for child windows.
+    pub fn position(mut self, position: Point) -> Self {
+        self.position = Some((position.x, position.y));
+        self
+    }
+
+    pub fn size(mut self, size: Size) -> Self {
+        self.size = Some((size.cx, size.cy));
+        self
+    }
+
+    pub fn instance(mut self, instance: HINSTANCE) -> Self {
+        self.instance = instance;
+        self
+    }
+
+    /// The `lpCreateParams` passed with WM_NCCREATE and WM_CREATE. Cannot
+    /// be combined with `state` or `handler`, or used with a class
+    /// registered with `WindowClassBuilder::handler`, which use it
+    /// themselves.
+    pub fn param(mut self, param: LPVOID) -> Self {
+        self.param = param;
+        self
+    }
+
+    /// Creates a message-only window, which is never visible and only
+    /// receives messages sent or posted to it.
+    pub fn message_only(mut self) -> Self {
+        self.message_only = true;
+        self
+    }
+
+    /// Attaches state to the window, to be handed to a window procedure
+    /// built with `window_proc!(state: S, ...)`. The state is dropped on
+    /// WM_NCDESTROY.
+    pub fn state<S: 'static>(mut self, state: Box<S>) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Attaches the handler for a window class registered with
+    /// `WindowClassBuilder::handler::<H>()`.
+    pub fn handler<H: WindowHandler>(self, handler: H) -> Self {
+        self.state(Box::new(handler))
+    }
+
     /// ECMA-234 Clause 27 CreateWindow CreateWindowEx
-    pub fn create(self) -> Result<AnyWindow<strategy::Foreign>> {
+    ///
+    /// The window belongs to the thread `ui` was obtained on.
+    pub fn create(mut self, ui: &UiThread) -> Result<AnyWindow<strategy::Foreign>> {
         use std::ptr::{null, null_mut};
         use winapi::um::winuser::CreateWindowExW;
         use winapi::um::winuser::CW_USEDEFAULT;
-        let window = unsafe {
-            let position = self.position.clone().unwrap_or((CW_USEDEFAULT, 0));
-            let size = self.size.clone().unwrap_or((CW_USEDEFAULT, 0));
+        use winapi::um::winuser::HWND_MESSAGE;
+        ui.check()?;
+        self.validate()?;
+        let mut handoff = WindowStateHandoff {
+            state: self.state.take(),
+        };
+        let has_state = handoff.state.is_some();
+        let window = with_pending_handoff(&mut handoff, |handoff_param| unsafe {
+            let param = if has_state { handoff_param } else { self.param };
+            let position = self.position.unwrap_or((CW_USEDEFAULT, 0));
+            let size = self.size.unwrap_or((CW_USEDEFAULT, 0));
             let h = CreateWindowExW(
                 self.style.1,
                 self.class.as_ptr_or_atom_ptr(),
@@ -378,20 +503,75 @@ impl<'a, 'b> WindowBuilder<'a, 'b> {
                 position.1,
                 size.0,
                 size.1,
-                self.parent.map_or_else(null_mut, |v| v.0),
+                if self.message_only {
+                    HWND_MESSAGE
+                } else {
+                    self.parent.map_or_else(null_mut, |v| v.0)
+                },
                 self.menu
                     .as_ref()
                     .map_or_else(null_mut, MenuOrChildWindowId::as_either_ptr),
                 self.instance,
-                self.param,
+                param,
             );
             if h.is_null() {
                 return Error::last();
             };
-            h
-        };
+            Ok(h)
+        })?;
+        if handoff.state.is_some() {
+            warn!(target: "apiw", "Window state was not claimed by the window procedure");
+        }
         ForeignWindow::new_from_attached(window).ok_or_last_error()
     }
+
+    /// Like `create`, but the window is destroyed when the returned value is
+    /// dropped, unless it has been destroyed by then.
+    pub fn create_owned(self, ui: &UiThread) -> Result<OwnedWindow> {
+        let window = self.create(ui)?;
+        let hwnd = window.data_ref().raw_handle();
+        if let Err(e) = track_window(hwnd) {
+            let _ = window.destroy();
+            return Err(e);
+        }
+        Ok(strategy::Local::attached_entity(WindowInner(hwnd)))
+    }
+
+    /// Rejects combinations CreateWindowExW would refuse or misinterpret.
+    fn validate(&self) -> Result<()> {
+        use winapi::shared::winerror::{ERROR_INVALID_PARAMETER, ERROR_TLW_WITH_WSCHILD};
+        let is_child = WindowStyles::from_bits_truncate(self.style.0).contains(WindowStyles::CHILD);
+        let invalid = || Err(Error::from_code(ERROR_INVALID_PARAMETER));
+        if is_child && self.parent.is_none() && !self.message_only {
+            return Err(Error::from_code(ERROR_TLW_WITH_WSCHILD));
+        }
+        match self.menu {
+            Some(MenuOrChildWindowId::ChildWindowId(_)) if !is_child => return invalid(),
+            Some(MenuOrChildWindowId::Menu(_)) if is_child => return invalid(),
+            _ => {}
+        }
+        if self.message_only && self.parent.is_some() {
+            return invalid();
+        }
+        if !self.param.is_null() && (self.state.is_some() || self.is_handler_class()) {
+            return invalid();
+        }
+        Ok(())
+    }
+
+    fn is_handler_class(&self) -> bool {
+        use winapi::um::winuser::{GetClassInfoExW, WNDCLASSEXW};
+        let atom = match self.class {
+            WindowClassInner::Atom(atom) => *atom,
+            WindowClassInner::String(name) => unsafe {
+                let mut wcex: WNDCLASSEXW = std::mem::zeroed();
+                wcex.cbSize = std::mem::size_of::<WNDCLASSEXW>() as _;
+                // Returns the class atom on success.
+                GetClassInfoExW(self.instance, name.as_ptr(), &mut wcex) as ATOM
+            },
+        };
+        atom != 0 && HANDLER_CLASSES.lock().unwrap().contains(&atom)
+    }
 }
 
 bitflags! {
@@ -475,6 +655,7 @@ impl<T: ManagedStrategy> AnyWindow<T> {
     /// ECMA-234 Clause 41 ShowWindow
     pub fn show_and_get_prev_state(&self, cmd: c_int, prev_state: &mut bool) -> Result<&Self> {
         use winapi::um::winuser::ShowWindow;
+        check_window_thread(self.data_ref().raw_handle())?;
         unsafe {
             let r = ShowWindow(self.data_ref().raw_handle(), cmd);
             *prev_state = booleanize(r);
@@ -568,6 +749,7 @@ impl<T: ManagedStrategy> AnyWindow<T> {
             | SWP_NOSIZE
             | SWP_NOZORDER;
         full_flags &= !SWP_NOSIZE;
+        check_window_thread(self.data_ref().raw_handle())?;
 
         unsafe {
             if !booleanize(SetWindowPos(
@@ -575,8 +757,8 @@ impl<T: ManagedStrategy> AnyWindow<T> {
                 null_mut(),
                 -1,
                 -1,
-                size.0.cx,
-                size.0.cy,
+                size.cx,
+                size.cy,
                 full_flags,
             )) {
                 return Error::last();
@@ -585,8 +767,37 @@ impl<T: ManagedStrategy> AnyWindow<T> {
         Ok(self)
     }
 
+    /// Requests a single WM_MOUSELEAVE when the cursor leaves the client
+    /// area. Tracking ends once the message is posted, so call this again
+    /// from the next mouse move to keep receiving it.
+    pub fn track_mouse_leave(&self) -> Result<&Self> {
+        use winapi::um::winuser::TrackMouseEvent;
+        use winapi::um::winuser::{TME_LEAVE, TRACKMOUSEEVENT};
+        check_window_thread(self.data_ref().raw_handle())?;
+        let mut event = TRACKMOUSEEVENT {
+            cbSize: std::mem::size_of::<TRACKMOUSEEVENT>() as DWORD,
+            dwFlags: TME_LEAVE,
+            hwndTrack: self.data_ref().raw_handle(),
+            dwHoverTime: 0,
+        };
+        unsafe {
+            if !booleanize(TrackMouseEvent(&mut event)) {
+                return Error::last();
+            }
+        }
+        Ok(self)
+    }
+
+    /// Called while the window's own state procedure is handling a message,
+    /// such as from `WindowHandler::on_close`, this only marks the window
+    /// for destruction once that call returns, so that `WM_DESTROY` and
+    /// `WM_NCDESTROY` still reach the state.
     pub fn destroy(&self) -> Result<()> {
         use winapi::um::winuser::DestroyWindow;
+        check_window_thread(self.data_ref().raw_handle())?;
+        if defer_destroy(self.data_ref().raw_handle()) {
+            return Ok(());
+        }
         unsafe {
             if !booleanize(DestroyWindow(self.data_ref().raw_handle())) {
                 return Error::last();
@@ -596,90 +807,29 @@ impl<T: ManagedStrategy> AnyWindow<T> {
     }
 }
 
-pub enum WindowProcResponse {
-    Done(LRESULT),
-    Fallback,
-}
-
-#[derive(Copy, Clone)]
-pub struct WindowProcRequestArgs {
-    pub msg: UINT,
-    pub wparam: WPARAM,
-    pub lparam: LPARAM,
-}
-
-pub struct CreateEventArgs<'a>(pub &'a WindowProcRequestArgs);
-
-use crate::graphics_subsystem::Point;
-
-pub struct MouseEventArgs<'a>(pub &'a WindowProcRequestArgs);
-
-#[repr(u32)]
-pub enum MouseEventArgType {
-    LeftButtonDown = winapi::um::winuser::WM_LBUTTONDOWN,
-    LeftButtonUp = winapi::um::winuser::WM_LBUTTONUP,
-    RightButtonDown = winapi::um::winuser::WM_RBUTTONDOWN,
-    RightButtonUp = winapi::um::winuser::WM_RBUTTONUP,
-    MiddleButtonDown = winapi::um::winuser::WM_MBUTTONDOWN,
-    MiddleButtonUp = winapi::um::winuser::WM_MBUTTONUP,
+pub use crate::windows_subsystem::window_message::WindowProcRequestArgs;
+pub use crate::windows_subsystem::window_message::{MouseModifiers, WheelDelta};
+pub use crate::windows_subsystem::window_route::{AnyWindowProcRequest, RouteTarget};
+pub use crate::windows_subsystem::window_route::{CharEventArgs, CommandEventArgs};
+pub use crate::windows_subsystem::window_route::{CreateEventArgs, WindowProcResponse};
+pub use crate::windows_subsystem::window_route::{KeyEventArgType, KeyEventArgs};
+pub use crate::windows_subsystem::window_route::{MouseEventArgType, MouseEventArgs};
 
-    #[doc(hidden)]
-    #[allow(non_camel_case_types)]
-    __non_exhuastive,
-}
+use crate::windows_subsystem::window_message::MessageTime;
 
-impl<'a> MouseEventArgs<'a> {
-    pub fn kind(&self) -> Option<MouseEventArgType> {
-        use winapi::um::winuser::{
-            WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MBUTTONDOWN, WM_MBUTTONUP, WM_RBUTTONDOWN,
-            WM_RBUTTONUP,
-        };
+impl RouteTarget for HWND {
+    type Window = ForeignWindow;
 
-        match self.0.msg {
-            WM_LBUTTONDOWN => Some(MouseEventArgType::LeftButtonDown),
-            WM_LBUTTONUP => Some(MouseEventArgType::LeftButtonUp),
-            WM_RBUTTONDOWN => Some(MouseEventArgType::RightButtonDown),
-            WM_RBUTTONUP => Some(MouseEventArgType::RightButtonUp),
-            WM_MBUTTONDOWN => Some(MouseEventArgType::MiddleButtonDown),
-            WM_MBUTTONUP => Some(MouseEventArgType::MiddleButtonUp),
-            _ => None,
-        }
+    fn window(self) -> Option<ForeignWindow> {
+        ForeignWindow::new_from_attached(self)
     }
-    pub fn cursor_coordinate(&self) -> Option<Point> {
-        use winapi::shared::windowsx::{GET_X_LPARAM, GET_Y_LPARAM};
-        use winapi::um::winuser::{WM_MOUSEACTIVATE, WM_MOUSELEAVE};
-        match self.0.msg {
-            WM_MOUSEACTIVATE | WM_MOUSELEAVE => None,
-            _ => Some(Point::new(
-                GET_X_LPARAM(self.0.lparam) as _,
-                GET_Y_LPARAM(self.0.lparam) as _,
-            )),
-        }
+
+    fn id(self) -> usize {
+        self as usize
     }
 }
 
-pub struct CommandEventArgs<'a>(pub &'a WindowProcRequestArgs);
-
 impl<'a> CommandEventArgs<'a> {
-    pub fn id(&self) -> WORD {
-        use winapi::shared::minwindef::LOWORD;
-        LOWORD(self.0.wparam as u32)
-    }
-
-    pub fn is_menu_command(&self) -> bool {
-        use winapi::shared::minwindef::HIWORD;
-        HIWORD(self.0.wparam as u32) == 0 && self.0.lparam == 0
-    }
-
-    pub fn is_accelerator_command(&self) -> bool {
-        use winapi::shared::minwindef::HIWORD;
-        HIWORD(self.0.wparam as u32) == 1 && self.0.lparam == 0
-    }
-
-    pub fn is_control_command(&self) -> bool {
-        self.0.lparam != 0
-    }
-
     pub fn control_window(&self) -> Option<ForeignWindow> {
         if self.is_control_command() {
             Some(ForeignWindow::new_from_attached(self.0.lparam as HWND)?)
@@ -689,158 +839,36 @@ impl<'a> CommandEventArgs<'a> {
     }
 }
 
-pub struct WindowProcRequest<'a> {
-    pub hwnd: HWND,
-    pub args: WindowProcRequestArgs,
-    pub response: Option<&'a mut WindowProcResponse>,
-}
-
-impl<'a> WindowProcRequest<'a> {
-    pub fn route_create<F>(&mut self, f: F) -> &mut Self
-    where
-        F: for<'r, 's> FnOnce(&'r ForeignWindow, CreateEventArgs<'s>) -> Result<bool>,
-    {
-        use winapi::um::winuser::WM_CREATE;
-        if self.args.msg == WM_CREATE {
-            if let Some(response) = self.response.take() {
-                if let Some(window) = ForeignWindow::new_from_attached(self.hwnd) {
-                    let create_args = CreateEventArgs(&self.args);
-                    if let Ok(r) = (f)(&window, create_args) {
-                        let r = if r { 0 } else { -1 };
-                        *response = WindowProcResponse::Done(r);
-                    }
-                } else {
-                    warn!(target: "apiw", "Received message without window target for event: {}",
-                          "route_create");
-                }
-            } else {
-                warn!(target: "apiw", "Duplicate route for event: {}",
-                      "route_create");
-            }
-        }
-        self
-    }
-
-    pub fn route_paint<F>(&mut self, f: F) -> &mut Self
-    where
-        F: for<'r> FnOnce(&'r ForeignWindow) -> Result<()>,
-    {
-        use winapi::um::winuser::WM_PAINT;
-        if self.args.msg == WM_PAINT {
-            if let Some(response) = self.response.take() {
-                if let Some(window) = ForeignWindow::new_from_attached(self.hwnd) {
-                    if (f)(&window).is_ok() {
-                        *response = WindowProcResponse::Done(0);
-                    }
-                } else {
-                    warn!(target: "apiw", "Received message without window target for event: {}",
-                          "route_paint");
-                }
-            } else {
-                warn!(target: "apiw", "Duplicate route for event: {}",
-                    "route_paint");
-            }
-        }
-        self
-    }
-
-    pub fn route_close<F>(&mut self, f: F) -> &mut Self
-    where
-        F: for<'r> FnOnce(&'r ForeignWindow) -> Result<()>,
-    {
-        use winapi::um::winuser::WM_CLOSE;
-        if self.args.msg == WM_CLOSE {
-            if let Some(response) = self.response.take() {
-                if let Some(window) = ForeignWindow::new_from_attached(self.hwnd) {
-                    if (f)(&window).is_ok() {
-                        *response = WindowProcResponse::Done(0);
-                    }
-                } else {
-                    warn!(target: "apiw", "Received message without window target for event: {}",
-                          "route_close");
-                }
-            } else {
-                warn!(target: "apiw", "Duplicate route for event: {}",
-                      "route_close");
-            }
-        }
-        self
-    }
-
-    pub fn route_destroy<F>(&mut self, f: F) -> &mut Self
-    where
-        F: for<'r> FnOnce(&'r ForeignWindow) -> Result<()>,
-    {
-        use winapi::um::winuser::WM_DESTROY;
-        if self.args.msg == WM_DESTROY {
-            if let Some(response) = self.response.take() {
-                if let Some(window) = ForeignWindow::new_from_attached(self.hwnd) {
-                    if (f)(&window).is_ok() {
-                        *response = WindowProcResponse::Done(0);
-                    }
-                } else {
-                    warn!(target: "apiw", "Received message without window target for event: {}",
-                          "route_destroy");
-                }
-            } else {
-                warn!(target: "apiw", "Duplicate route for event: {}",
-                      "route_destroy");
-            }
-        }
-        self
-    }
-
-    pub fn route_command<F>(&mut self, f: F) -> &mut Self
-    where
-        F: for<'r, 's> FnOnce(&'r ForeignWindow, CommandEventArgs<'s>) -> Result<()>,
-    {
-        use winapi::um::winuser::WM_COMMAND;
-        if self.args.msg == WM_COMMAND {
-            if let Some(response) = self.response.take() {
-                if let Some(window) = ForeignWindow::new_from_attached(self.hwnd) {
-                    let command_args = CommandEventArgs(&self.args);
-                    if (f)(&window, command_args).is_ok() {
-                        *response = WindowProcResponse::Done(0);
-                    }
-                } else {
-                    warn!(target: "apiw", "Received message without window target for event: {}",
-                          "route_command");
-                }
-            } else {
-                warn!(target: "apiw", "Duplicate route for event: {}",
-                      "route_command");
-            }
-        }
-        self
-    }
-
-    pub fn route_mouse<F>(&mut self, f: F) -> &mut Self
-    where
-        F: for<'r, 's> FnOnce(&'r ForeignWindow, MouseEventArgs<'s>) -> Result<bool>,
-    {
-        use winapi::um::winuser::{WM_MOUSEFIRST, WM_MOUSELAST};
-        if self.args.msg >= WM_MOUSEFIRST && self.args.msg <= WM_MOUSELAST {
-            if let Some(response) = self.response.take() {
-                if let Some(window) = ForeignWindow::new_from_attached(self.hwnd) {
-                    let mouse_args = MouseEventArgs(&self.args);
-                    if let Ok(true) = (f)(&window, mouse_args) {
-                        *response = WindowProcResponse::Done(0);
-                    }
-                } else {
-                    warn!(target: "apiw", "Received message without window target for event: {}",
-                          "route_mouse");
-                }
-            } else {
-                warn!(target: "apiw", "Duplicate route for event: {}",
-                      "route_mouse");
-            }
-        }
-        self
-    }
-}
+pub type WindowProcRequest<'a> = AnyWindowProcRequest<'a, HWND>;
 
 #[macro_export]
 macro_rules! window_proc {
+    (state: $state:ty, $nest_proc:expr, reentrant: $reentrant_proc:expr) => {{
+        unsafe extern "system" fn translator(
+            hwnd: $crate::full_windows_api::shared::windef::HWND,
+            msg: $crate::full_windows_api::shared::minwindef::UINT,
+            wparam: $crate::full_windows_api::shared::minwindef::WPARAM,
+            lparam: $crate::full_windows_api::shared::minwindef::LPARAM,
+        ) -> $crate::full_windows_api::shared::minwindef::LRESULT {
+            $crate::windows_subsystem::window_state::translate_with_state_reentrant::<$state, _, _>(
+                hwnd, msg, wparam, lparam, $nest_proc, $reentrant_proc,
+            )
+        }
+        translator
+    }};
+    (state: $state:ty, $nest_proc:expr) => {{
+        unsafe extern "system" fn translator(
+            hwnd: $crate::full_windows_api::shared::windef::HWND,
+            msg: $crate::full_windows_api::shared::minwindef::UINT,
+            wparam: $crate::full_windows_api::shared::minwindef::WPARAM,
+            lparam: $crate::full_windows_api::shared::minwindef::LPARAM,
+        ) -> $crate::full_windows_api::shared::minwindef::LRESULT {
+            $crate::windows_subsystem::window_state::translate_with_state::<$state, _>(
+                hwnd, msg, wparam, lparam, $nest_proc,
+            )
+        }
+        translator
+    }};
     ($nest_proc:expr) => {{
         unsafe extern "system" fn translator(
             hwnd: $crate::full_windows_api::shared::windef::HWND,
@@ -864,17 +892,22 @@ macro_rules! window_proc {
             }
 
             use $crate::windows_subsystem::window::WindowProcResponse;
-            match response {
+            let r = match response {
                 WindowProcResponse::Done(r) => r,
                 WindowProcResponse::Fallback => {
                     $crate::full_windows_api::um::winuser::DefWindowProcW(hwnd, msg, wparam, lparam)
                 }
+            };
+            if msg == $crate::full_windows_api::um::winuser::WM_NCDESTROY {
+                $crate::windows_subsystem::weak_window::notify_destroyed(hwnd);
             }
+            r
         }
         translator
     }};
 }
 
+use crate::windows_subsystem::timer::{timer_interval_millis, unregister_timer};
 use std::num::NonZeroUsize;
 use std::time::Duration;
 
@@ -888,19 +921,12 @@ impl<T: ManagedStrategy> AnyWindow<T> {
         timer_proc: TimerProcInner,
     ) -> Result<&Self> {
         use winapi::um::winuser::SetTimer;
-        use winapi::um::winuser::USER_TIMER_MAXIMUM;
+        check_window_thread(self.data_ref().raw_handle())?;
         unsafe {
-            let mut interval = interval
-                .as_secs()
-                .saturating_mul(60)
-                .saturating_add(interval.subsec_millis() as _);
-            if interval > USER_TIMER_MAXIMUM as _ {
-                interval = USER_TIMER_MAXIMUM as _;
-            }
             if 0 == SetTimer(
                 self.data_ref().raw_handle(),
                 id.get(),
-                interval as _,
+                timer_interval_millis(interval),
                 Some(timer_proc),
             ) {
                 return Error::last();
@@ -908,10 +934,46 @@ impl<T: ManagedStrategy> AnyWindow<T> {
         }
         Ok(self)
     }
+
+    /// Sets a timer without a timer procedure, so `WM_TIMER` reaches the
+    /// window procedure; see `WindowProcRequest::route_timer`.
+    pub fn set_message_timer(&self, id: NonZeroUsize, interval: Duration) -> Result<&Self> {
+        use winapi::um::winuser::SetTimer;
+        check_window_thread(self.data_ref().raw_handle())?;
+        unsafe {
+            if 0 == SetTimer(
+                self.data_ref().raw_handle(),
+                id.get(),
+                timer_interval_millis(interval),
+                None,
+            ) {
+                return Error::last();
+            }
+        }
+        Ok(self)
+    }
+
+    /// Stops the timer `id`, also dropping its closure if it was set with
+    /// `set_timer`.
+    pub fn kill_timer(&self, id: NonZeroUsize) -> Result<&Self> {
+        use winapi::um::winuser::KillTimer;
+        let hwnd = self.data_ref().raw_handle();
+        check_window_thread(hwnd)?;
+        unregister_timer(hwnd, id.get());
+        unsafe {
+            if !booleanize(KillTimer(hwnd, id.get())) {
+                return Error::last();
+            }
+        }
+        Ok(self)
+    }
 }
 
 pub struct TimerProcRequest {
     pub hwnd: HWND,
+    pub id: usize,
+    /// The system tick count when the timer fired.
+    pub tick: MessageTime,
 }
 
 impl TimerProcRequest {
@@ -926,11 +988,15 @@ macro_rules! timer_proc {
         unsafe extern "system" fn translator(
             hwnd: $crate::full_windows_api::shared::windef::HWND,
             arg2: $crate::full_windows_api::shared::minwindef::UINT,
-            arg3: $crate::full_windows_api::shared::basetsd::UINT_PTR,
-            arg4: $crate::full_windows_api::shared::minwindef::DWORD,
+            id: $crate::full_windows_api::shared::basetsd::UINT_PTR,
+            tick: $crate::full_windows_api::shared::minwindef::DWORD,
         ) {
             {
-                let request = $crate::windows_subsystem::window::TimerProcRequest { hwnd };
+                let request = $crate::windows_subsystem::window::TimerProcRequest {
+                    hwnd,
+                    id,
+                    tick: $crate::windows_subsystem::window_message::MessageTime::from_ticks(tick),
+                };
 
                 ($nest_proc)(request);
             }
@@ -955,6 +1021,7 @@ impl<T: ManagedStrategy> AnyWindow<T> {
         use winapi::um::winuser::ReleaseCapture;
         use winapi::um::winuser::SetCapture;
         if let Some(wnd) = window {
+            check_window_thread(wnd.data_ref().raw_handle())?;
             unsafe {
                 let h = wnd.data_ref().raw_handle();
                 let _ = SetCapture(h);
@@ -974,6 +1041,9 @@ impl<T: ManagedStrategy> AnyWindow<T> {
     pub fn swap_captured(window: &mut Option<ForeignWindow>) -> Result<()> {
         use winapi::um::winuser::ReleaseCapture;
         use winapi::um::winuser::SetCapture;
+        if let Some(wnd) = window.as_ref() {
+            check_window_thread(wnd.data_ref().raw_handle())?;
+        }
         if let Some(wnd) = window.take() {
             unsafe {
                 let h = wnd.data_ref().raw_handle();
@@ -11,6 +11,7 @@ use crate::shared::{ManagedData, ManagedEntity};
 
 use crate::graphics_subsystem::device_context::LocalDeviceContext;
 use crate::graphics_subsystem::device_context::{DeviceContextInner, DeviceContextInnerKind};
+use crate::windows_subsystem::ui_thread::check_window_thread;
 use crate::windows_subsystem::window::AnyWindow;
 
 pub type AnyPaintDeviceContext<T> = ManagedEntity<PaintDeviceContextInner, T>;
@@ -74,6 +75,7 @@ impl<T: ManagedStrategy> AnyWindow<T> {
     pub fn do_paint(&self) -> Result<AnyPaintDeviceContext<strategy::Local>> {
         use std::mem::zeroed;
         use winapi::um::winuser::BeginPaint;
+        check_window_thread(self.data_ref().raw_handle())?;
         let paint_dc: PaintDeviceContextInner = unsafe {
             let hwnd = self.data_ref().raw_handle();
             let mut paint_structure = zeroed();
@@ -98,12 +100,14 @@ impl<T: ManagedStrategy> AnyWindow<T> {
     /// ECMA-234 Clause 156 UpdateWindow
     pub fn update(&self) -> Result<&Self> {
         use winapi::um::winuser::UpdateWindow;
+        check_window_thread(self.data_ref().raw_handle())?;
         unsafe {
             UpdateWindow(self.data_ref().raw_handle());
         }
         Ok(self)
     }
 
+    /// Like `invalidate_and_erase`, may be called from any thread.
     pub fn invalidate(&self) -> Result<&Self> {
         use std::ptr::null;
         use winapi::shared::minwindef::FALSE;
@@ -0,0 +1,172 @@
+//! Trait-based window procedures, as an alternative to `window_proc!`.
+//!
+//! Register the class with `WindowClassBuilder::handler::<H>()` and attach
+//! the handler value with `WindowBuilder::handler`. Each `on_*` method
+//! returns `Ok(true)` when it handled the message; `Ok(false)` or an error
+//! falls back to `DefWindowProcW`.
+
+use winapi::shared::minwindef::{LPARAM, LRESULT, UINT, WPARAM};
+use winapi::shared::windef::HWND;
+
+use crate::graphics_subsystem::Size;
+use crate::windows_subsystem::window::ForeignWindow;
+use crate::windows_subsystem::window::WindowProcRequestArgs;
+use crate::windows_subsystem::window_route::{combine_char_code_unit, is_surrogate_pair};
+use crate::windows_subsystem::window::{CharEventArgs, CommandEventArgs, CreateEventArgs};
+use crate::windows_subsystem::window::{KeyEventArgs, MouseEventArgs};
+use crate::windows_subsystem::window::{WindowProcRequest, WindowProcResponse};
+use crate::windows_subsystem::window_message::{SizeKind, WindowMessage};
+use crate::windows_subsystem::window_state::translate_with_state_reentrant;
+use crate::Result;
+
+#[allow(unused_variables)]
+pub trait WindowHandler: 'static {
+    /// Return `Ok(false)` to abort window creation.
+    fn on_create(&mut self, window: &ForeignWindow, args: CreateEventArgs) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn on_paint(&mut self, window: &ForeignWindow) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Calling `window.destroy()` from here destroys the window once this
+    /// returns, so `on_destroy` is still called.
+    fn on_close(&mut self, window: &ForeignWindow) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn on_destroy(&mut self, window: &ForeignWindow) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn on_command(&mut self, window: &ForeignWindow, args: CommandEventArgs) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn on_mouse(&mut self, window: &ForeignWindow, args: MouseEventArgs) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn on_key(&mut self, window: &ForeignWindow, args: KeyEventArgs) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn on_char(&mut self, window: &ForeignWindow, args: CharEventArgs) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn on_size(&mut self, window: &ForeignWindow, kind: SizeKind, size: Size) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn on_timer(&mut self, window: &ForeignWindow, id: usize) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Called for every message without a dedicated method above.
+    fn on_message(
+        &mut self,
+        window: &ForeignWindow,
+        args: &WindowProcRequestArgs,
+    ) -> WindowProcResponse {
+        WindowProcResponse::Fallback
+    }
+
+    /// Called instead of the methods above for messages that arrive while
+    /// one of them is still running on the same window, for example ones
+    /// sent from a handler or dispatched by a modal loop it runs.
+    fn on_reentrant_message(
+        window: &ForeignWindow,
+        args: &WindowProcRequestArgs,
+    ) -> WindowProcResponse
+    where
+        Self: Sized,
+    {
+        WindowProcResponse::Fallback
+    }
+}
+
+fn dispatch_reentrant<H: WindowHandler>(mut request: WindowProcRequest) {
+    if let Some(response) = request.response.take() {
+        if let Some(window) = ForeignWindow::new_from_attached(request.hwnd) {
+            *response = H::on_reentrant_message(&window, &request.args);
+        }
+    }
+}
+
+fn dispatch<H: WindowHandler>(handler: &mut H, mut request: WindowProcRequest) {
+    let response = match request.response.take() {
+        Some(response) => response,
+        None => return,
+    };
+    let window = match ForeignWindow::new_from_attached(request.hwnd) {
+        Some(window) => window,
+        None => return,
+    };
+    let args = &request.args;
+    let handled = match args.decode() {
+        WindowMessage::Create { .. } => {
+            if let Ok(r) = handler.on_create(&window, CreateEventArgs(args)) {
+                *response = WindowProcResponse::Done(if r { 0 } else { -1 });
+            }
+            return;
+        }
+        WindowMessage::Paint => handler.on_paint(&window),
+        WindowMessage::Close => handler.on_close(&window),
+        WindowMessage::Destroy => handler.on_destroy(&window),
+        WindowMessage::Command { .. } => handler.on_command(&window, CommandEventArgs(args)),
+        WindowMessage::MouseMove { .. }
+        | WindowMessage::MouseButtonDown { .. }
+        | WindowMessage::MouseButtonUp { .. }
+        | WindowMessage::MouseButtonDoubleClick { .. }
+        | WindowMessage::MouseWheel { .. }
+        | WindowMessage::MouseHorizontalWheel { .. }
+        | WindowMessage::MouseLeave => handler.on_mouse(&window, MouseEventArgs(args)),
+        WindowMessage::KeyDown { .. }
+        | WindowMessage::KeyUp { .. }
+        | WindowMessage::SysKeyDown { .. }
+        | WindowMessage::SysKeyUp { .. } => handler.on_key(&window, KeyEventArgs(args)),
+        WindowMessage::Char { code_unit, .. } => {
+            match combine_char_code_unit(request.hwnd, code_unit) {
+                // the first half of a pair was already answered, so the pair is
+                // never left half to the default processing.
+                Some(character) => {
+                    let handled = handler.on_char(&window, CharEventArgs::new(args, character));
+                    if is_surrogate_pair(character) {
+                        Ok(true)
+                    } else {
+                        handled
+                    }
+                }
+                None => Ok(true),
+            }
+        }
+        WindowMessage::Size { kind, size } => handler.on_size(&window, kind, size),
+        WindowMessage::Timer { id, .. } => handler.on_timer(&window, id),
+        _ => {
+            *response = handler.on_message(&window, args);
+            return;
+        }
+    };
+    if let Ok(true) = handled {
+        *response = WindowProcResponse::Done(args.handled_result());
+    }
+}
+
+/// The window procedure registered by `WindowClassBuilder::handler::<H>()`.
+pub(crate) unsafe extern "system" fn handler_window_proc<H: WindowHandler>(
+    hwnd: HWND,
+    msg: UINT,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    translate_with_state_reentrant::<H, _, _>(
+        hwnd,
+        msg,
+        wparam,
+        lparam,
+        |request, handler| dispatch(handler, request),
+        dispatch_reentrant::<H>,
+    )
+}
@@ -0,0 +1,977 @@
+use bitflags::bitflags;
+use derive_more::From;
+use std::fmt::{self, Debug, Formatter};
+use std::ops::Sub;
+use std::time::Duration;
+
+use crate::graphics_subsystem::Point;
+use crate::graphics_subsystem::Size;
+use crate::windows_subsystem::keyboard::VirtualKey;
+use crate::windows_subsystem::wm;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct WindowProcRequestArgs {
+    pub msg: u32,
+    pub wparam: usize,
+    pub lparam: isize,
+}
+
+#[inline]
+pub(crate) fn loword(v: usize) -> u16 {
+    v as u16
+}
+
+#[inline]
+pub(crate) fn hiword(v: usize) -> u16 {
+    (v >> 16) as u16
+}
+
+/// Unpacks a pair of signed 16-bit coordinates, as `GET_X_LPARAM` and
+/// `GET_Y_LPARAM` do.
+#[inline]
+pub(crate) fn point_from_lparam(lparam: isize) -> Point {
+    Point::new(
+        loword(lparam as usize) as i16 as isize,
+        hiword(lparam as usize) as i16 as isize,
+    )
+}
+
+/// The cursor position of a message, packed as GetMessagePos returns it.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, From)]
+pub struct MessagePos(u32);
+
+impl MessagePos {
+    /// Packs a position, truncating each coordinate to 16 bits.
+    pub fn from_point(point: Point) -> Self {
+        let x = point.x() as u16 as u32;
+        let y = point.y() as u16 as u32;
+        MessagePos(x | (y << 16))
+    }
+
+    pub fn raw(&self) -> u32 {
+        self.0
+    }
+
+    /// Screen coordinates; negative on monitors left of or above the
+    /// primary one.
+    pub fn point(&self) -> Point {
+        point_from_lparam(self.0 as isize)
+    }
+}
+
+impl From<MessagePos> for Point {
+    fn from(pos: MessagePos) -> Point {
+        pos.point()
+    }
+}
+
+/// The time of a message, in milliseconds since the system started. The
+/// count wraps around roughly every 49.7 days, so compare times with
+/// `elapsed_since` rather than by value.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, From)]
+pub struct MessageTime(i32);
+
+impl MessageTime {
+    pub fn from_ticks(ticks: u32) -> Self {
+        MessageTime(ticks as i32)
+    }
+
+    pub fn ticks(&self) -> u32 {
+        self.0 as u32
+    }
+
+    /// Time from `earlier` to `self`, assuming less than one wraparound
+    /// period lies between them.
+    pub fn elapsed_since(&self, earlier: MessageTime) -> Duration {
+        Duration::from_millis(u64::from(self.ticks().wrapping_sub(earlier.ticks())))
+    }
+}
+
+impl Sub for MessageTime {
+    type Output = Duration;
+
+    fn sub(self, earlier: MessageTime) -> Duration {
+        self.elapsed_since(earlier)
+    }
+}
+
+/// An untyped handle carried in a message parameter.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct RawHandle(pub usize);
+
+impl RawHandle {
+    pub const NULL: RawHandle = RawHandle(0);
+
+    pub fn is_null(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl Debug for RawHandle {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "RawHandle({:#x})", self.0)
+    }
+}
+
+/// The keystroke information packed into the `lparam` of keyboard messages.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct KeystrokeFlags(u32);
+
+impl KeystrokeFlags {
+    pub fn from_lparam(lparam: isize) -> Self {
+        KeystrokeFlags(lparam as u32)
+    }
+
+    pub fn repeat_count(&self) -> u16 {
+        self.0 as u16
+    }
+
+    pub fn scan_code(&self) -> u8 {
+        (self.0 >> 16) as u8
+    }
+
+    pub fn is_extended_key(&self) -> bool {
+        self.0 & (1 << 24) != 0
+    }
+
+    pub fn is_alt_down(&self) -> bool {
+        self.0 & (1 << 29) != 0
+    }
+
+    pub fn was_previously_down(&self) -> bool {
+        self.0 & (1 << 30) != 0
+    }
+
+    pub fn is_being_released(&self) -> bool {
+        self.0 & (1 << 31) != 0
+    }
+}
+
+impl Debug for KeystrokeFlags {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        f.debug_struct("KeystrokeFlags")
+            .field("repeat_count", &self.repeat_count())
+            .field("scan_code", &self.scan_code())
+            .field("extended", &self.is_extended_key())
+            .field("alt_down", &self.is_alt_down())
+            .field("previously_down", &self.was_previously_down())
+            .field("being_released", &self.is_being_released())
+            .finish()
+    }
+}
+
+bitflags! {
+    #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+    pub struct MouseModifiers : u16 {
+        const LBUTTON = 0x0001;
+        const RBUTTON = 0x0002;
+        const SHIFT = 0x0004;
+        const CONTROL = 0x0008;
+        const MBUTTON = 0x0010;
+        const XBUTTON1 = 0x0020;
+        const XBUTTON2 = 0x0040;
+    }
+}
+
+/// Signed wheel rotation, in multiples or fractions of `WHEEL_DELTA`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct WheelDelta(pub i16);
+
+impl WheelDelta {
+    pub const WHEEL_DELTA: i16 = 120;
+
+    pub fn raw(&self) -> i16 {
+        self.0
+    }
+
+    /// Rotation in detents; positive is away from the user (or to the right
+    /// for the horizontal wheel). High-resolution wheels report fractions.
+    pub fn notches(&self) -> f32 {
+        f32::from(self.0) / f32::from(Self::WHEEL_DELTA)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    X1,
+    X2,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum SizeKind {
+    Restored,
+    Minimized,
+    Maximized,
+    MaxShow,
+    MaxHide,
+    Other(u32),
+}
+
+impl SizeKind {
+    fn from_wparam(wparam: usize) -> Self {
+        match wparam {
+            0 => SizeKind::Restored,
+            1 => SizeKind::Minimized,
+            2 => SizeKind::Maximized,
+            3 => SizeKind::MaxShow,
+            4 => SizeKind::MaxHide,
+            v => SizeKind::Other(v as u32),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ActivateKind {
+    Inactive,
+    Active,
+    ClickActive,
+    Other(u16),
+}
+
+impl ActivateKind {
+    fn from_word(v: u16) -> Self {
+        match v {
+            0 => ActivateKind::Inactive,
+            1 => ActivateKind::Active,
+            2 => ActivateKind::ClickActive,
+            v => ActivateKind::Other(v),
+        }
+    }
+}
+
+/// The `SB_*` request of a scroll bar message. Horizontal requests share the
+/// values of their vertical counterparts (`SB_LINELEFT` is `SB_LINEUP`, and so on).
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ScrollRequest {
+    LineUp,
+    LineDown,
+    PageUp,
+    PageDown,
+    ThumbPosition,
+    ThumbTrack,
+    Top,
+    Bottom,
+    EndScroll,
+    Other(u16),
+}
+
+impl ScrollRequest {
+    fn from_word(v: u16) -> Self {
+        match v {
+            0 => ScrollRequest::LineUp,
+            1 => ScrollRequest::LineDown,
+            2 => ScrollRequest::PageUp,
+            3 => ScrollRequest::PageDown,
+            4 => ScrollRequest::ThumbPosition,
+            5 => ScrollRequest::ThumbTrack,
+            6 => ScrollRequest::Top,
+            7 => ScrollRequest::Bottom,
+            8 => ScrollRequest::EndScroll,
+            v => ScrollRequest::Other(v),
+        }
+    }
+}
+
+/// A window message with its parameters unpacked.
+///
+/// Pointer parameters (such as `CREATESTRUCT` or `MINMAXINFO`) are kept as
+/// addresses, since they can only be dereferenced inside the window procedure.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum WindowMessage {
+    Null,
+    NcCreate {
+        create_struct: usize,
+    },
+    Create {
+        create_struct: usize,
+    },
+    Destroy,
+    NcDestroy,
+    Move {
+        position: Point,
+    },
+    Size {
+        kind: SizeKind,
+        size: Size,
+    },
+    Activate {
+        kind: ActivateKind,
+        minimized: bool,
+        other: RawHandle,
+    },
+    SetFocus {
+        previous: RawHandle,
+    },
+    KillFocus {
+        next: RawHandle,
+    },
+    Enable {
+        enabled: bool,
+    },
+    Paint,
+    Close,
+    Quit {
+        exit_code: i32,
+    },
+    EraseBackground {
+        dc: RawHandle,
+    },
+    ShowWindow {
+        shown: bool,
+        status: u32,
+    },
+    SetCursor {
+        window: RawHandle,
+        hit_test: i16,
+        mouse_msg: u16,
+    },
+    GetMinMaxInfo {
+        min_max_info: usize,
+    },
+    Notify {
+        control_id: usize,
+        header: usize,
+    },
+    ContextMenu {
+        window: RawHandle,
+        position: Point,
+    },
+    KeyDown {
+        key: VirtualKey,
+        flags: KeystrokeFlags,
+    },
+    KeyUp {
+        key: VirtualKey,
+        flags: KeystrokeFlags,
+    },
+    SysKeyDown {
+        key: VirtualKey,
+        flags: KeystrokeFlags,
+    },
+    SysKeyUp {
+        key: VirtualKey,
+        flags: KeystrokeFlags,
+    },
+    Char {
+        code_unit: u16,
+        flags: KeystrokeFlags,
+    },
+    DeadChar {
+        code_unit: u16,
+        flags: KeystrokeFlags,
+    },
+    SysChar {
+        code_unit: u16,
+        flags: KeystrokeFlags,
+    },
+    SysDeadChar {
+        code_unit: u16,
+        flags: KeystrokeFlags,
+    },
+    UniChar {
+        code_point: u32,
+        flags: KeystrokeFlags,
+    },
+    Command {
+        id: u16,
+        notification_code: u16,
+        control: RawHandle,
+    },
+    SysCommand {
+        command: u32,
+        position: Point,
+    },
+    Timer {
+        id: usize,
+        timer_proc: usize,
+    },
+    HorizontalScroll {
+        request: ScrollRequest,
+        position: u16,
+        scroll_bar: RawHandle,
+    },
+    VerticalScroll {
+        request: ScrollRequest,
+        position: u16,
+        scroll_bar: RawHandle,
+    },
+    MouseMove {
+        position: Point,
+        modifiers: MouseModifiers,
+    },
+    MouseButtonDown {
+        button: MouseButton,
+        position: Point,
+        modifiers: MouseModifiers,
+    },
+    MouseButtonUp {
+        button: MouseButton,
+        position: Point,
+        modifiers: MouseModifiers,
+    },
+    MouseButtonDoubleClick {
+        button: MouseButton,
+        position: Point,
+        modifiers: MouseModifiers,
+    },
+    /// The position is in screen coordinates.
+    MouseWheel {
+        delta: WheelDelta,
+        position: Point,
+        modifiers: MouseModifiers,
+    },
+    /// The position is in screen coordinates.
+    MouseHorizontalWheel {
+        delta: WheelDelta,
+        position: Point,
+        modifiers: MouseModifiers,
+    },
+    MouseHover {
+        position: Point,
+        modifiers: MouseModifiers,
+    },
+    MouseLeave,
+    CaptureChanged {
+        new_capture: RawHandle,
+    },
+    DropFiles {
+        drop: RawHandle,
+    },
+    Other(WindowProcRequestArgs),
+}
+
+impl WindowProcRequestArgs {
+    pub fn decode(&self) -> WindowMessage {
+        let wparam = self.wparam;
+        let lparam = self.lparam;
+        let keystroke = || KeystrokeFlags::from_lparam(lparam);
+        let modifiers = || MouseModifiers::from_bits_retain(loword(wparam));
+
+        match self.msg {
+            wm::WM_NULL => WindowMessage::Null,
+            wm::WM_NCCREATE => WindowMessage::NcCreate {
+                create_struct: lparam as usize,
+            },
+            wm::WM_CREATE => WindowMessage::Create {
+                create_struct: lparam as usize,
+            },
+            wm::WM_DESTROY => WindowMessage::Destroy,
+            wm::WM_NCDESTROY => WindowMessage::NcDestroy,
+            wm::WM_MOVE => WindowMessage::Move {
+                position: point_from_lparam(lparam),
+            },
+            wm::WM_SIZE => WindowMessage::Size {
+                kind: SizeKind::from_wparam(wparam),
+                size: Size::new(
+                    loword(lparam as usize) as usize,
+                    hiword(lparam as usize) as usize,
+                ),
+            },
+            wm::WM_ACTIVATE => WindowMessage::Activate {
+                kind: ActivateKind::from_word(loword(wparam)),
+                minimized: hiword(wparam) != 0,
+                other: RawHandle(lparam as usize),
+            },
+            wm::WM_SETFOCUS => WindowMessage::SetFocus {
+                previous: RawHandle(wparam),
+            },
+            wm::WM_KILLFOCUS => WindowMessage::KillFocus {
+                next: RawHandle(wparam),
+            },
+            wm::WM_ENABLE => WindowMessage::Enable {
+                enabled: wparam != 0,
+            },
+            wm::WM_PAINT => WindowMessage::Paint,
+            wm::WM_CLOSE => WindowMessage::Close,
+            wm::WM_QUIT => WindowMessage::Quit {
+                exit_code: wparam as i32,
+            },
+            wm::WM_ERASEBKGND => WindowMessage::EraseBackground {
+                dc: RawHandle(wparam),
+            },
+            wm::WM_SHOWWINDOW => WindowMessage::ShowWindow {
+                shown: wparam != 0,
+                status: lparam as u32,
+            },
+            wm::WM_SETCURSOR => WindowMessage::SetCursor {
+                window: RawHandle(wparam),
+                hit_test: loword(lparam as usize) as i16,
+                mouse_msg: hiword(lparam as usize),
+            },
+            wm::WM_GETMINMAXINFO => WindowMessage::GetMinMaxInfo {
+                min_max_info: lparam as usize,
+            },
+            wm::WM_NOTIFY => WindowMessage::Notify {
+                control_id: wparam,
+                header: lparam as usize,
+            },
+            wm::WM_CONTEXTMENU => WindowMessage::ContextMenu {
+                window: RawHandle(wparam),
+                position: point_from_lparam(lparam),
+            },
+            wm::WM_KEYDOWN => WindowMessage::KeyDown {
+                key: VirtualKey::from_code(wparam as u8),
+                flags: keystroke(),
+            },
+            wm::WM_KEYUP => WindowMessage::KeyUp {
+                key: VirtualKey::from_code(wparam as u8),
+                flags: keystroke(),
+            },
+            wm::WM_SYSKEYDOWN => WindowMessage::SysKeyDown {
+                key: VirtualKey::from_code(wparam as u8),
+                flags: keystroke(),
+            },
+            wm::WM_SYSKEYUP => WindowMessage::SysKeyUp {
+                key: VirtualKey::from_code(wparam as u8),
+                flags: keystroke(),
+            },
+            wm::WM_CHAR => WindowMessage::Char {
+                code_unit: loword(wparam),
+                flags: keystroke(),
+            },
+            wm::WM_DEADCHAR => WindowMessage::DeadChar {
+                code_unit: loword(wparam),
+                flags: keystroke(),
+            },
+            wm::WM_SYSCHAR => WindowMessage::SysChar {
+                code_unit: loword(wparam),
+                flags: keystroke(),
+            },
+            wm::WM_SYSDEADCHAR => WindowMessage::SysDeadChar {
+                code_unit: loword(wparam),
+                flags: keystroke(),
+            },
+            wm::WM_UNICHAR => WindowMessage::UniChar {
+                code_point: wparam as u32,
+                flags: keystroke(),
+            },
+            wm::WM_COMMAND => WindowMessage::Command {
+                id: loword(wparam),
+                notification_code: hiword(wparam),
+                control: RawHandle(lparam as usize),
+            },
+            wm::WM_SYSCOMMAND => WindowMessage::SysCommand {
+                command: wparam as u32 & 0xFFF0,
+                position: point_from_lparam(lparam),
+            },
+            wm::WM_TIMER => WindowMessage::Timer {
+                id: wparam,
+                timer_proc: lparam as usize,
+            },
+            wm::WM_HSCROLL => WindowMessage::HorizontalScroll {
+                request: ScrollRequest::from_word(loword(wparam)),
+                position: hiword(wparam),
+                scroll_bar: RawHandle(lparam as usize),
+            },
+            wm::WM_VSCROLL => WindowMessage::VerticalScroll {
+                request: ScrollRequest::from_word(loword(wparam)),
+                position: hiword(wparam),
+                scroll_bar: RawHandle(lparam as usize),
+            },
+            wm::WM_MOUSEMOVE => WindowMessage::MouseMove {
+                position: point_from_lparam(lparam),
+                modifiers: modifiers(),
+            },
+            wm::WM_LBUTTONDOWN..=wm::WM_MBUTTONDBLCLK
+            | wm::WM_XBUTTONDOWN
+            | wm::WM_XBUTTONUP
+            | wm::WM_XBUTTONDBLCLK => {
+                let button = match self.msg {
+                    wm::WM_LBUTTONDOWN | wm::WM_LBUTTONUP | wm::WM_LBUTTONDBLCLK => {
+                        MouseButton::Left
+                    }
+                    wm::WM_RBUTTONDOWN | wm::WM_RBUTTONUP | wm::WM_RBUTTONDBLCLK => {
+                        MouseButton::Right
+                    }
+                    wm::WM_MBUTTONDOWN | wm::WM_MBUTTONUP | wm::WM_MBUTTONDBLCLK => {
+                        MouseButton::Middle
+                    }
+                    _ => match hiword(wparam) {
+                        1 => MouseButton::X1,
+                        2 => MouseButton::X2,
+                        _ => return WindowMessage::Other(*self),
+                    },
+                };
+                let position = point_from_lparam(lparam);
+                let modifiers = modifiers();
+                match self.msg {
+                    wm::WM_LBUTTONDOWN
+                    | wm::WM_RBUTTONDOWN
+                    | wm::WM_MBUTTONDOWN
+                    | wm::WM_XBUTTONDOWN => WindowMessage::MouseButtonDown {
+                        button,
+                        position,
+                        modifiers,
+                    },
+                    wm::WM_LBUTTONUP | wm::WM_RBUTTONUP | wm::WM_MBUTTONUP | wm::WM_XBUTTONUP => {
+                        WindowMessage::MouseButtonUp {
+                            button,
+                            position,
+                            modifiers,
+                        }
+                    }
+                    _ => WindowMessage::MouseButtonDoubleClick {
+                        button,
+                        position,
+                        modifiers,
+                    },
+                }
+            }
+            wm::WM_MOUSEWHEEL => WindowMessage::MouseWheel {
+                delta: WheelDelta(hiword(wparam) as i16),
+                position: point_from_lparam(lparam),
+                modifiers: modifiers(),
+            },
+            wm::WM_MOUSEHWHEEL => WindowMessage::MouseHorizontalWheel {
+                delta: WheelDelta(hiword(wparam) as i16),
+                position: point_from_lparam(lparam),
+                modifiers: modifiers(),
+            },
+            wm::WM_MOUSEHOVER => WindowMessage::MouseHover {
+                position: point_from_lparam(lparam),
+                modifiers: modifiers(),
+            },
+            wm::WM_MOUSELEAVE => WindowMessage::MouseLeave,
+            wm::WM_CAPTURECHANGED => WindowMessage::CaptureChanged {
+                new_capture: RawHandle(lparam as usize),
+            },
+            wm::WM_DROPFILES => WindowMessage::DropFiles {
+                drop: RawHandle(wparam),
+            },
+            _ => WindowMessage::Other(*self),
+        }
+    }
+
+    /// The result a window procedure returns after handling the message
+    /// itself. `WM_XBUTTON*` must return `TRUE`, or the system also
+    /// synthesizes `WM_APPCOMMAND`.
+    pub fn handled_result(&self) -> isize {
+        match self.msg {
+            wm::WM_XBUTTONDOWN | wm::WM_XBUTTONUP | wm::WM_XBUTTONDBLCLK => 1,
+            _ => 0,
+        }
+    }
+
+    /// Whether `wparam` and `lparam` are plain values rather than pointers,
+    /// so the message can be sent to a window without pointing at anything.
+    /// Only system messages known to carry no pointers qualify; the meaning
+    /// of `WM_USER` and `WM_APP` messages is up to the application.
+    pub fn is_pointer_free(&self) -> bool {
+        match self.msg {
+            wm::WM_NULL
+            | wm::WM_MOVE
+            | wm::WM_SIZE
+            | wm::WM_ACTIVATE
+            | wm::WM_SETFOCUS
+            | wm::WM_KILLFOCUS
+            | wm::WM_ENABLE
+            | wm::WM_PAINT
+            | wm::WM_CLOSE
+            | wm::WM_QUIT
+            | wm::WM_SHOWWINDOW
+            | wm::WM_CANCELMODE
+            | wm::WM_COMMAND
+            | wm::WM_SYSCOMMAND
+            | wm::WM_MOUSELEAVE => true,
+            // The `lparam` of a timer message may be a `TIMERPROC`.
+            wm::WM_TIMER => self.lparam == 0,
+            msg => {
+                wm::MessageRange::KEYBOARD.contains(msg) || wm::MessageRange::MOUSE.contains(msg)
+            }
+        }
+    }
+}
+
+impl From<WindowProcRequestArgs> for WindowMessage {
+    fn from(args: WindowProcRequestArgs) -> Self {
+        args.decode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(msg: u32, wparam: usize, lparam: isize) -> WindowProcRequestArgs {
+        WindowProcRequestArgs {
+            msg,
+            wparam,
+            lparam,
+        }
+    }
+
+    fn make_lparam(lo: u16, hi: u16) -> isize {
+        ((hi as u32) << 16 | lo as u32) as i32 as isize
+    }
+
+    #[test]
+    fn decodes_known_encodings() {
+        let table = vec![
+            (args(wm::WM_NULL, 0, 0), WindowMessage::Null),
+            (args(wm::WM_CLOSE, 0, 0), WindowMessage::Close),
+            (
+                args(wm::WM_QUIT, -3i32 as usize, 0),
+                WindowMessage::Quit { exit_code: -3 },
+            ),
+            (
+                args(wm::WM_MOVE, 0, make_lparam(-20i16 as u16, 300)),
+                WindowMessage::Move {
+                    position: Point::new(-20, 300),
+                },
+            ),
+            (
+                args(wm::WM_SIZE, 2, make_lparam(640, 480)),
+                WindowMessage::Size {
+                    kind: SizeKind::Maximized,
+                    size: Size::new(640, 480),
+                },
+            ),
+            (
+                args(wm::WM_SIZE, 9, make_lparam(1, 2)),
+                WindowMessage::Size {
+                    kind: SizeKind::Other(9),
+                    size: Size::new(1, 2),
+                },
+            ),
+            (
+                args(wm::WM_ACTIVATE, 0x0001_0002, 0x1234),
+                WindowMessage::Activate {
+                    kind: ActivateKind::ClickActive,
+                    minimized: true,
+                    other: RawHandle(0x1234),
+                },
+            ),
+            (
+                args(wm::WM_SETFOCUS, 0xABC, 0),
+                WindowMessage::SetFocus {
+                    previous: RawHandle(0xABC),
+                },
+            ),
+            (
+                args(wm::WM_KILLFOCUS, 0, 0),
+                WindowMessage::KillFocus {
+                    next: RawHandle::NULL,
+                },
+            ),
+            (
+                args(wm::WM_KEYDOWN, 0x41, 0x001E_0001),
+                WindowMessage::KeyDown {
+                    key: VirtualKey::A,
+                    flags: KeystrokeFlags::from_lparam(0x001E_0001),
+                },
+            ),
+            (
+                args(wm::WM_CHAR, 0x61, 0x001E_0001),
+                WindowMessage::Char {
+                    code_unit: 0x61,
+                    flags: KeystrokeFlags::from_lparam(0x001E_0001),
+                },
+            ),
+            (
+                args(
+                    wm::WM_MOUSEWHEEL,
+                    make_lparam(0x0008, -240i16 as u16) as usize,
+                    make_lparam(100, -5i16 as u16),
+                ),
+                WindowMessage::MouseWheel {
+                    delta: WheelDelta(-240),
+                    position: Point::new(100, -5),
+                    modifiers: MouseModifiers::CONTROL,
+                },
+            ),
+            (
+                args(wm::WM_MOUSEHWHEEL, 0x0078_0000, 0),
+                WindowMessage::MouseHorizontalWheel {
+                    delta: WheelDelta(120),
+                    position: Point::ORIGIN,
+                    modifiers: MouseModifiers::empty(),
+                },
+            ),
+            (
+                args(wm::WM_LBUTTONDOWN, 0x0005, make_lparam(10, 20)),
+                WindowMessage::MouseButtonDown {
+                    button: MouseButton::Left,
+                    position: Point::new(10, 20),
+                    modifiers: MouseModifiers::LBUTTON | MouseModifiers::SHIFT,
+                },
+            ),
+            (
+                args(wm::WM_RBUTTONDBLCLK, 0x0002, make_lparam(1, 1)),
+                WindowMessage::MouseButtonDoubleClick {
+                    button: MouseButton::Right,
+                    position: Point::new(1, 1),
+                    modifiers: MouseModifiers::RBUTTON,
+                },
+            ),
+            (
+                args(wm::WM_XBUTTONUP, 0x0002_0000, make_lparam(3, 4)),
+                WindowMessage::MouseButtonUp {
+                    button: MouseButton::X2,
+                    position: Point::new(3, 4),
+                    modifiers: MouseModifiers::empty(),
+                },
+            ),
+            (
+                args(wm::WM_XBUTTONDOWN, 0x0003_0000, 0),
+                WindowMessage::Other(args(wm::WM_XBUTTONDOWN, 0x0003_0000, 0)),
+            ),
+            (
+                args(wm::WM_VSCROLL, 0x0032_0005, 0x77),
+                WindowMessage::VerticalScroll {
+                    request: ScrollRequest::ThumbTrack,
+                    position: 0x32,
+                    scroll_bar: RawHandle(0x77),
+                },
+            ),
+            (
+                args(wm::WM_HSCROLL, 8, 0),
+                WindowMessage::HorizontalScroll {
+                    request: ScrollRequest::EndScroll,
+                    position: 0,
+                    scroll_bar: RawHandle::NULL,
+                },
+            ),
+            (
+                args(wm::WM_TIMER, 7, 0x4000),
+                WindowMessage::Timer {
+                    id: 7,
+                    timer_proc: 0x4000,
+                },
+            ),
+            (
+                args(
+                    wm::WM_SETCURSOR,
+                    0x99,
+                    make_lparam(-2i16 as u16, wm::WM_LBUTTONDOWN as u16),
+                ),
+                WindowMessage::SetCursor {
+                    window: RawHandle(0x99),
+                    hit_test: -2,
+                    mouse_msg: wm::WM_LBUTTONDOWN as u16,
+                },
+            ),
+            (
+                args(wm::WM_GETMINMAXINFO, 0, 0x5000),
+                WindowMessage::GetMinMaxInfo {
+                    min_max_info: 0x5000,
+                },
+            ),
+            (
+                args(wm::WM_NOTIFY, 1001, 0x6000),
+                WindowMessage::Notify {
+                    control_id: 1001,
+                    header: 0x6000,
+                },
+            ),
+            (
+                args(wm::WM_DROPFILES, 0x42, 0),
+                WindowMessage::DropFiles {
+                    drop: RawHandle(0x42),
+                },
+            ),
+            (
+                args(wm::WM_COMMAND, 0x0001_0064, 0),
+                WindowMessage::Command {
+                    id: 100,
+                    notification_code: 1,
+                    control: RawHandle::NULL,
+                },
+            ),
+            (
+                args(wm::WM_SYSCOMMAND, 0xF063, make_lparam(5, 6)),
+                WindowMessage::SysCommand {
+                    command: 0xF060,
+                    position: Point::new(5, 6),
+                },
+            ),
+            (
+                args(wm::WM_CONTEXTMENU, 0x10, -1),
+                WindowMessage::ContextMenu {
+                    window: RawHandle(0x10),
+                    position: Point::new(-1, -1),
+                },
+            ),
+            (args(wm::WM_MOUSELEAVE, 0, 0), WindowMessage::MouseLeave),
+            (
+                args(wm::WM_USER + 5, 1, 2),
+                WindowMessage::Other(args(wm::WM_USER + 5, 1, 2)),
+            ),
+        ];
+
+        for (args, expected) in table {
+            assert_eq!(args.decode(), expected, "decoding {:?}", args);
+        }
+    }
+
+    #[test]
+    fn converts_wheel_delta_to_notches() {
+        assert_eq!(WheelDelta(120).notches(), 1.0);
+        assert_eq!(WheelDelta(-240).notches(), -2.0);
+        assert_eq!(WheelDelta(30).notches(), 0.25);
+    }
+
+    #[test]
+    fn decodes_message_pos() {
+        let pos = MessagePos::from(0xFFF6_FFECu32);
+        assert_eq!(pos.point(), Point::new(-20, -10));
+        assert_eq!(
+            Point::from(MessagePos::from(0x0040_0020u32)),
+            Point::new(32, 64)
+        );
+        assert_eq!(MessagePos::from_point(Point::new(-20, -10)), pos);
+    }
+
+    #[test]
+    fn measures_message_time_across_wraparound() {
+        let earlier = MessageTime::from_ticks(0xFFFF_FF00);
+        let later = MessageTime::from_ticks(0x100);
+        assert_eq!(later.elapsed_since(earlier), Duration::from_millis(0x200));
+        assert_eq!(later - earlier, Duration::from_millis(0x200));
+        assert_eq!(
+            MessageTime::from(1500) - MessageTime::from(500),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn unpacks_keystroke_flags() {
+        let flags = KeystrokeFlags::from_lparam(0xE14B_0003u32 as i32 as isize);
+        assert_eq!(flags.repeat_count(), 3);
+        assert_eq!(flags.scan_code(), 0x4B);
+        assert!(flags.is_extended_key());
+        assert!(flags.is_alt_down());
+        assert!(flags.was_previously_down());
+        assert!(flags.is_being_released());
+
+        let flags = KeystrokeFlags::from_lparam(0x0001_0001);
+        assert_eq!(flags.scan_code(), 0x01);
+        assert!(!flags.is_extended_key());
+        assert!(!flags.was_previously_down());
+        assert!(!flags.is_being_released());
+    }
+
+    #[test]
+    fn x_button_messages_return_true_when_handled() {
+        for msg in [wm::WM_XBUTTONDOWN, wm::WM_XBUTTONUP, wm::WM_XBUTTONDBLCLK] {
+            assert_eq!(args(msg, 0x0001_0000, 0).handled_result(), 1);
+        }
+        assert_eq!(args(wm::WM_LBUTTONDOWN, 0, 0).handled_result(), 0);
+        assert_eq!(args(wm::WM_MOUSEMOVE, 0, 0).handled_result(), 0);
+    }
+
+    #[test]
+    fn only_value_messages_are_pointer_free() {
+        assert!(args(wm::WM_CLOSE, 0, 0).is_pointer_free());
+        assert!(args(wm::WM_KEYDOWN, 0x41, 1).is_pointer_free());
+        assert!(args(wm::WM_LBUTTONDOWN, 1, 0x0020_0010).is_pointer_free());
+        assert!(args(wm::WM_TIMER, 7, 0).is_pointer_free());
+        assert!(!args(wm::WM_TIMER, 7, 0x1000).is_pointer_free());
+        assert!(!args(wm::WM_SETTEXT, 0, 0x1000).is_pointer_free());
+        assert!(!args(wm::WM_CREATE, 0, 0x1000).is_pointer_free());
+        assert!(!args(wm::WM_USER, 0, 0).is_pointer_free());
+        assert!(!args(wm::WM_APP, 0, 0).is_pointer_free());
+    }
+}
@@ -0,0 +1,541 @@
+//! Routing of window procedure messages to closures.
+//!
+//! The routes only need a way to resolve the window a message is for, so
+//! they work the same for real windows and for the simulated queue of
+//! `extensions::simulation`.
+
+use log::warn;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+
+use crate::graphics_subsystem::Point;
+use crate::shared::Result;
+use crate::windows_subsystem::keyboard::SurrogatePairCombiner;
+use crate::windows_subsystem::keyboard::VirtualKey;
+use crate::windows_subsystem::user_message::{MessagePayload, UserMessage};
+use crate::windows_subsystem::window_message::KeystrokeFlags;
+use crate::windows_subsystem::window_message::WindowProcRequestArgs;
+use crate::windows_subsystem::window_message::{hiword, loword, point_from_lparam};
+use crate::windows_subsystem::window_message::{MouseModifiers, WheelDelta};
+use crate::windows_subsystem::wm;
+
+/// Identifies the window a message is for.
+pub trait RouteTarget: Copy {
+    /// What the route closures are given.
+    type Window;
+
+    /// `None` if the window no longer exists.
+    fn window(self) -> Option<Self::Window>;
+
+    /// A key that tells windows apart.
+    fn id(self) -> usize;
+}
+
+pub enum WindowProcResponse {
+    Done(isize),
+    Fallback,
+}
+
+pub struct CreateEventArgs<'a>(pub &'a WindowProcRequestArgs);
+
+pub struct MouseEventArgs<'a>(pub &'a WindowProcRequestArgs);
+
+#[repr(u32)]
+#[allow(clippy::manual_non_exhaustive)]
+pub enum MouseEventArgType {
+    Move = wm::WM_MOUSEMOVE,
+    LeftButtonDown = wm::WM_LBUTTONDOWN,
+    LeftButtonUp = wm::WM_LBUTTONUP,
+    LeftButtonDoubleClick = wm::WM_LBUTTONDBLCLK,
+    RightButtonDown = wm::WM_RBUTTONDOWN,
+    RightButtonUp = wm::WM_RBUTTONUP,
+    RightButtonDoubleClick = wm::WM_RBUTTONDBLCLK,
+    MiddleButtonDown = wm::WM_MBUTTONDOWN,
+    MiddleButtonUp = wm::WM_MBUTTONUP,
+    MiddleButtonDoubleClick = wm::WM_MBUTTONDBLCLK,
+    Wheel = wm::WM_MOUSEWHEEL,
+    HorizontalWheel = wm::WM_MOUSEHWHEEL,
+    Leave = wm::WM_MOUSELEAVE,
+    // The X button messages share one message code each, so these are
+    // numbered past the end of the WM_* range.
+    XButton1Down = 0x1_0000,
+    XButton1Up,
+    XButton1DoubleClick,
+    XButton2Down,
+    XButton2Up,
+    XButton2DoubleClick,
+
+    #[doc(hidden)]
+    #[allow(non_camel_case_types)]
+    __non_exhuastive,
+}
+
+impl<'a> MouseEventArgs<'a> {
+    pub fn kind(&self) -> Option<MouseEventArgType> {
+        use crate::windows_subsystem::wm::{
+            WM_LBUTTONDBLCLK, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MBUTTONDBLCLK, WM_MBUTTONDOWN,
+            WM_MBUTTONUP, WM_MOUSEHWHEEL, WM_MOUSELEAVE, WM_MOUSEMOVE, WM_MOUSEWHEEL,
+            WM_RBUTTONDBLCLK, WM_RBUTTONDOWN, WM_RBUTTONUP, WM_XBUTTONDBLCLK, WM_XBUTTONDOWN,
+            WM_XBUTTONUP,
+        };
+        const XBUTTON1: u16 = 0x0001;
+        const XBUTTON2: u16 = 0x0002;
+
+        let xbutton = (self.0.wparam >> 16) as u16;
+        match (self.0.msg, xbutton) {
+            (WM_MOUSEMOVE, _) => Some(MouseEventArgType::Move),
+            (WM_LBUTTONDOWN, _) => Some(MouseEventArgType::LeftButtonDown),
+            (WM_LBUTTONUP, _) => Some(MouseEventArgType::LeftButtonUp),
+            (WM_LBUTTONDBLCLK, _) => Some(MouseEventArgType::LeftButtonDoubleClick),
+            (WM_RBUTTONDOWN, _) => Some(MouseEventArgType::RightButtonDown),
+            (WM_RBUTTONUP, _) => Some(MouseEventArgType::RightButtonUp),
+            (WM_RBUTTONDBLCLK, _) => Some(MouseEventArgType::RightButtonDoubleClick),
+            (WM_MBUTTONDOWN, _) => Some(MouseEventArgType::MiddleButtonDown),
+            (WM_MBUTTONUP, _) => Some(MouseEventArgType::MiddleButtonUp),
+            (WM_MBUTTONDBLCLK, _) => Some(MouseEventArgType::MiddleButtonDoubleClick),
+            (WM_MOUSEWHEEL, _) => Some(MouseEventArgType::Wheel),
+            (WM_MOUSEHWHEEL, _) => Some(MouseEventArgType::HorizontalWheel),
+            (WM_MOUSELEAVE, _) => Some(MouseEventArgType::Leave),
+            (WM_XBUTTONDOWN, XBUTTON1) => Some(MouseEventArgType::XButton1Down),
+            (WM_XBUTTONUP, XBUTTON1) => Some(MouseEventArgType::XButton1Up),
+            (WM_XBUTTONDBLCLK, XBUTTON1) => Some(MouseEventArgType::XButton1DoubleClick),
+            (WM_XBUTTONDOWN, XBUTTON2) => Some(MouseEventArgType::XButton2Down),
+            (WM_XBUTTONUP, XBUTTON2) => Some(MouseEventArgType::XButton2Up),
+            (WM_XBUTTONDBLCLK, XBUTTON2) => Some(MouseEventArgType::XButton2DoubleClick),
+            _ => None,
+        }
+    }
+
+    /// Cursor position. Client coordinates, except for the wheel messages
+    /// which report screen coordinates.
+    pub fn cursor_coordinate(&self) -> Option<Point> {
+        use crate::windows_subsystem::wm::{WM_MOUSEACTIVATE, WM_MOUSELEAVE};
+        match self.0.msg {
+            WM_MOUSEACTIVATE | WM_MOUSELEAVE => None,
+            _ => Some(point_from_lparam(self.0.lparam)),
+        }
+    }
+
+    /// The MK_* key and button state that accompanies the message.
+    pub fn modifiers(&self) -> Option<MouseModifiers> {
+        use crate::windows_subsystem::wm::WM_MOUSELEAVE;
+        match self.0.msg {
+            WM_MOUSELEAVE => None,
+            _ => Some(MouseModifiers::from_bits_retain(self.0.wparam as u16)),
+        }
+    }
+
+    pub fn wheel_delta(&self) -> Option<WheelDelta> {
+        use crate::windows_subsystem::wm::{WM_MOUSEHWHEEL, WM_MOUSEWHEEL};
+        match self.0.msg {
+            WM_MOUSEWHEEL | WM_MOUSEHWHEEL => Some(WheelDelta((self.0.wparam >> 16) as u16 as i16)),
+            _ => None,
+        }
+    }
+}
+
+pub struct KeyEventArgs<'a>(pub &'a WindowProcRequestArgs);
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum KeyEventArgType {
+    KeyDown,
+    KeyUp,
+    SysKeyDown,
+    SysKeyUp,
+}
+
+impl<'a> KeyEventArgs<'a> {
+    pub fn kind(&self) -> Option<KeyEventArgType> {
+        use crate::windows_subsystem::wm::{WM_KEYDOWN, WM_KEYUP, WM_SYSKEYDOWN, WM_SYSKEYUP};
+
+        match self.0.msg {
+            WM_KEYDOWN => Some(KeyEventArgType::KeyDown),
+            WM_KEYUP => Some(KeyEventArgType::KeyUp),
+            WM_SYSKEYDOWN => Some(KeyEventArgType::SysKeyDown),
+            WM_SYSKEYUP => Some(KeyEventArgType::SysKeyUp),
+            _ => None,
+        }
+    }
+
+    pub fn virtual_key(&self) -> VirtualKey {
+        VirtualKey::from_code(self.0.wparam as u8)
+    }
+
+    pub fn flags(&self) -> KeystrokeFlags {
+        KeystrokeFlags::from_lparam(self.0.lparam)
+    }
+
+    pub fn repeat_count(&self) -> u16 {
+        self.flags().repeat_count()
+    }
+
+    pub fn scan_code(&self) -> u8 {
+        self.flags().scan_code()
+    }
+
+    pub fn is_extended_key(&self) -> bool {
+        self.flags().is_extended_key()
+    }
+
+    pub fn was_previously_down(&self) -> bool {
+        self.flags().was_previously_down()
+    }
+
+    pub fn is_being_released(&self) -> bool {
+        self.flags().is_being_released()
+    }
+}
+
+pub struct CharEventArgs<'a> {
+    args: &'a WindowProcRequestArgs,
+    character: char,
+}
+
+impl<'a> CharEventArgs<'a> {
+    pub(crate) fn new(args: &'a WindowProcRequestArgs, character: char) -> Self {
+        CharEventArgs { args, character }
+    }
+
+    pub fn args(&self) -> &WindowProcRequestArgs {
+        self.args
+    }
+
+    /// The character, combined from both halves when it arrived as a
+    /// surrogate pair.
+    pub fn character(&self) -> char {
+        self.character
+    }
+
+    pub fn flags(&self) -> KeystrokeFlags {
+        KeystrokeFlags::from_lparam(self.args.lparam)
+    }
+}
+
+thread_local! {
+    static CHAR_COMBINERS: RefCell<HashMap<usize, SurrogatePairCombiner>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Combines `WM_CHAR` code units per window, so a high surrogate pending
+/// for one window never pairs with a code unit sent to another.
+pub(crate) fn combine_char_code_unit<H: RouteTarget>(hwnd: H, code_unit: u16) -> Option<char> {
+    CHAR_COMBINERS.with(|combiners| {
+        let mut combiners = combiners.borrow_mut();
+        let mut state = combiners.remove(&hwnd.id()).unwrap_or_default();
+        let result = state.push(code_unit);
+        if state.has_pending() {
+            // So the pending half is forgotten even if the window's procedure
+            // does not report `WM_NCDESTROY`.
+            #[cfg(windows)]
+            let _ = crate::windows_subsystem::weak_window::install_destroy_hook();
+            combiners.insert(hwnd.id(), state);
+        }
+        result
+    })
+}
+
+/// Drops the pending half of a surrogate pair, so a recycled handle does
+/// not inherit it.
+pub(crate) fn forget_char_combiner<H: RouteTarget>(hwnd: H) {
+    let _ = CHAR_COMBINERS.try_with(|combiners| combiners.borrow_mut().remove(&hwnd.id()));
+}
+
+/// Whether `character` arrived as two `WM_CHAR` messages, the first of which
+/// was already answered.
+pub(crate) fn is_surrogate_pair(character: char) -> bool {
+    character.len_utf16() == 2
+}
+
+pub struct CommandEventArgs<'a>(pub &'a WindowProcRequestArgs);
+
+impl<'a> CommandEventArgs<'a> {
+    pub fn id(&self) -> u16 {
+        loword(self.0.wparam)
+    }
+
+    pub fn is_menu_command(&self) -> bool {
+        hiword(self.0.wparam) == 0 && self.0.lparam == 0
+    }
+
+    pub fn is_accelerator_command(&self) -> bool {
+        hiword(self.0.wparam) == 1 && self.0.lparam == 0
+    }
+
+    pub fn is_control_command(&self) -> bool {
+        self.0.lparam != 0
+    }
+}
+
+/// A message for a window procedure, with the slot its answer goes in.
+/// `H` identifies the window; see `WindowProcRequest` for real windows.
+pub struct AnyWindowProcRequest<'a, H> {
+    pub hwnd: H,
+    pub args: WindowProcRequestArgs,
+    pub response: Option<&'a mut WindowProcResponse>,
+}
+
+impl<'a, H: RouteTarget> AnyWindowProcRequest<'a, H> {
+    pub fn route_create<F>(&mut self, f: F) -> &mut Self
+    where
+        F: for<'r, 's> FnOnce(&'r H::Window, CreateEventArgs<'s>) -> Result<bool>,
+    {
+        use crate::windows_subsystem::wm::WM_CREATE;
+        if self.args.msg == WM_CREATE {
+            if let Some(response) = self.response.take() {
+                if let Some(window) = self.hwnd.window() {
+                    let create_args = CreateEventArgs(&self.args);
+                    if let Ok(r) = (f)(&window, create_args) {
+                        let r = if r { 0 } else { -1 };
+                        *response = WindowProcResponse::Done(r);
+                    }
+                } else {
+                    warn!(target: "apiw", "Received message without window target for event: {}",
+                          "route_create");
+                }
+            } else {
+                warn!(target: "apiw", "Duplicate route for event: {}",
+                      "route_create");
+            }
+        }
+        self
+    }
+
+    pub fn route_paint<F>(&mut self, f: F) -> &mut Self
+    where
+        F: for<'r> FnOnce(&'r H::Window) -> Result<()>,
+    {
+        use crate::windows_subsystem::wm::WM_PAINT;
+        if self.args.msg == WM_PAINT {
+            if let Some(response) = self.response.take() {
+                if let Some(window) = self.hwnd.window() {
+                    if (f)(&window).is_ok() {
+                        *response = WindowProcResponse::Done(0);
+                    }
+                } else {
+                    warn!(target: "apiw", "Received message without window target for event: {}",
+                          "route_paint");
+                }
+            } else {
+                warn!(target: "apiw", "Duplicate route for event: {}",
+                    "route_paint");
+            }
+        }
+        self
+    }
+
+    pub fn route_close<F>(&mut self, f: F) -> &mut Self
+    where
+        F: for<'r> FnOnce(&'r H::Window) -> Result<()>,
+    {
+        use crate::windows_subsystem::wm::WM_CLOSE;
+        if self.args.msg == WM_CLOSE {
+            if let Some(response) = self.response.take() {
+                if let Some(window) = self.hwnd.window() {
+                    if (f)(&window).is_ok() {
+                        *response = WindowProcResponse::Done(0);
+                    }
+                } else {
+                    warn!(target: "apiw", "Received message without window target for event: {}",
+                          "route_close");
+                }
+            } else {
+                warn!(target: "apiw", "Duplicate route for event: {}",
+                      "route_close");
+            }
+        }
+        self
+    }
+
+    pub fn route_destroy<F>(&mut self, f: F) -> &mut Self
+    where
+        F: for<'r> FnOnce(&'r H::Window) -> Result<()>,
+    {
+        use crate::windows_subsystem::wm::WM_DESTROY;
+        if self.args.msg == WM_DESTROY {
+            if let Some(response) = self.response.take() {
+                if let Some(window) = self.hwnd.window() {
+                    if (f)(&window).is_ok() {
+                        *response = WindowProcResponse::Done(0);
+                    }
+                } else {
+                    warn!(target: "apiw", "Received message without window target for event: {}",
+                          "route_destroy");
+                }
+            } else {
+                warn!(target: "apiw", "Duplicate route for event: {}",
+                      "route_destroy");
+            }
+        }
+        self
+    }
+
+    pub fn route_command<F>(&mut self, f: F) -> &mut Self
+    where
+        F: for<'r, 's> FnOnce(&'r H::Window, CommandEventArgs<'s>) -> Result<()>,
+    {
+        use crate::windows_subsystem::wm::WM_COMMAND;
+        if self.args.msg == WM_COMMAND {
+            if let Some(response) = self.response.take() {
+                if let Some(window) = self.hwnd.window() {
+                    let command_args = CommandEventArgs(&self.args);
+                    if (f)(&window, command_args).is_ok() {
+                        *response = WindowProcResponse::Done(0);
+                    }
+                } else {
+                    warn!(target: "apiw", "Received message without window target for event: {}",
+                          "route_command");
+                }
+            } else {
+                warn!(target: "apiw", "Duplicate route for event: {}",
+                      "route_command");
+            }
+        }
+        self
+    }
+
+    pub fn route_mouse<F>(&mut self, f: F) -> &mut Self
+    where
+        F: for<'r, 's> FnOnce(&'r H::Window, MouseEventArgs<'s>) -> Result<bool>,
+    {
+        use crate::windows_subsystem::wm::{WM_MOUSEFIRST, WM_MOUSELAST, WM_MOUSELEAVE};
+        if (self.args.msg >= WM_MOUSEFIRST && self.args.msg <= WM_MOUSELAST)
+            || self.args.msg == WM_MOUSELEAVE
+        {
+            if let Some(response) = self.response.take() {
+                if let Some(window) = self.hwnd.window() {
+                    let mouse_args = MouseEventArgs(&self.args);
+                    if let Ok(true) = (f)(&window, mouse_args) {
+                        *response = WindowProcResponse::Done(self.args.handled_result());
+                    }
+                } else {
+                    warn!(target: "apiw", "Received message without window target for event: {}",
+                          "route_mouse");
+                }
+            } else {
+                warn!(target: "apiw", "Duplicate route for event: {}",
+                      "route_mouse");
+            }
+        }
+        self
+    }
+
+    /// Routes a message allocated with `UserMessage`, handing over its
+    /// payload.
+    pub fn route_user_message<P, F>(&mut self, message: &UserMessage<P>, f: F) -> &mut Self
+    where
+        P: MessagePayload,
+        F: for<'r> FnOnce(&'r H::Window, P) -> Result<()>,
+    {
+        if let Some(payload) = message.decode(&self.args) {
+            if let Some(response) = self.response.take() {
+                if let Some(window) = self.hwnd.window() {
+                    if (f)(&window, payload).is_ok() {
+                        *response = WindowProcResponse::Done(0);
+                    }
+                } else {
+                    warn!(target: "apiw", "Received message without window target for event: {}",
+                          "route_user_message");
+                }
+            } else {
+                warn!(target: "apiw", "Duplicate route for event: {}",
+                      "route_user_message");
+            }
+        }
+        self
+    }
+
+    /// Routes `WM_TIMER` from timers set without a timer procedure, such as
+    /// with `set_message_timer`.
+    pub fn route_timer<F>(&mut self, f: F) -> &mut Self
+    where
+        F: for<'r> FnOnce(&'r H::Window, NonZeroUsize) -> Result<()>,
+    {
+        use crate::windows_subsystem::wm::WM_TIMER;
+        if self.args.msg == WM_TIMER {
+            if let Some(response) = self.response.take() {
+                if let Some(window) = self.hwnd.window() {
+                    if let Some(id) = NonZeroUsize::new(self.args.wparam) {
+                        if (f)(&window, id).is_ok() {
+                            *response = WindowProcResponse::Done(0);
+                        }
+                    }
+                } else {
+                    warn!(target: "apiw", "Received message without window target for event: {}",
+                          "route_timer");
+                }
+            } else {
+                warn!(target: "apiw", "Duplicate route for event: {}",
+                      "route_timer");
+            }
+        }
+        self
+    }
+
+    pub fn route_key<F>(&mut self, f: F) -> &mut Self
+    where
+        F: for<'r, 's> FnOnce(&'r H::Window, KeyEventArgs<'s>) -> Result<bool>,
+    {
+        use crate::windows_subsystem::wm::{WM_KEYDOWN, WM_KEYUP, WM_SYSKEYDOWN, WM_SYSKEYUP};
+        match self.args.msg {
+            WM_KEYDOWN | WM_KEYUP | WM_SYSKEYDOWN | WM_SYSKEYUP => {
+                if let Some(response) = self.response.take() {
+                    if let Some(window) = self.hwnd.window() {
+                        let key_args = KeyEventArgs(&self.args);
+                        if let Ok(true) = (f)(&window, key_args) {
+                            *response = WindowProcResponse::Done(0);
+                        }
+                    } else {
+                        warn!(target: "apiw", "Received message without window target for event: {}",
+                              "route_key");
+                    }
+                } else {
+                    warn!(target: "apiw", "Duplicate route for event: {}",
+                          "route_key");
+                }
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// Routes `WM_CHAR`. A high surrogate is held back (and the message
+    /// answered) until its low surrogate arrives, so the closure is called
+    /// once per character. Since the first half is already answered, a
+    /// character made of a surrogate pair is answered even when the closure
+    /// declines it, so default processing never sees half a pair.
+    pub fn route_char<F>(&mut self, f: F) -> &mut Self
+    where
+        F: for<'r, 's> FnOnce(&'r H::Window, CharEventArgs<'s>) -> Result<bool>,
+    {
+        use crate::windows_subsystem::wm::WM_CHAR;
+        if self.args.msg == WM_CHAR {
+            if let Some(response) = self.response.take() {
+                if let Some(window) = self.hwnd.window() {
+                    match combine_char_code_unit(self.hwnd, self.args.wparam as u16) {
+                        Some(character) => {
+                            let char_args = CharEventArgs {
+                                args: &self.args,
+                                character,
+                            };
+                            let handled = (f)(&window, char_args);
+                            if matches!(handled, Ok(true)) || is_surrogate_pair(character) {
+                                *response = WindowProcResponse::Done(0);
+                            }
+                        }
+                        None => {
+                            *response = WindowProcResponse::Done(0);
+                        }
+                    }
+                } else {
+                    warn!(target: "apiw", "Received message without window target for event: {}",
+                          "route_char");
+                }
+            } else {
+                warn!(target: "apiw", "Duplicate route for event: {}",
+                      "route_char");
+            }
+        }
+        self
+    }
+}
@@ -0,0 +1,216 @@
+//! Per-window Rust state, attached with `WindowBuilder::state` and reached
+//! from `window_proc!(state: T, ...)`.
+//!
+//! The state travels through `lpCreateParams` to `WM_NCCREATE`, is kept in
+//! `GWLP_USERDATA` for the lifetime of the window and is dropped on
+//! `WM_NCDESTROY`. Windows with attached state own their `GWLP_USERDATA`.
+//!
+//! A message that arrives while the state is borrowed by an outer call on
+//! the same window, such as one sent from a handler or dispatched by a
+//! modal loop a handler runs, cannot reach the state. It is passed to the
+//! reentrant procedure given as `window_proc!(state: T, proc, reentrant:
+//! reentrant_proc)` instead, and falls back to `DefWindowProcW` without
+//! one.
+
+use log::warn;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::ptr::null_mut;
+use winapi::shared::minwindef::{LPARAM, LPVOID, LRESULT, UINT, WPARAM};
+use winapi::shared::windef::HWND;
+
+use crate::windows_subsystem::weak_window::notify_destroyed;
+use crate::windows_subsystem::window::WindowProcResponse;
+use crate::windows_subsystem::window::{WindowProcRequest, WindowProcRequestArgs};
+
+/// Passed as `lpCreateParams` while `CreateWindowExW` runs.
+pub(crate) struct WindowStateHandoff {
+    pub(crate) state: Option<Box<dyn Any>>,
+}
+
+thread_local! {
+    static PENDING_HANDOFF: Cell<*mut WindowStateHandoff> = const { Cell::new(null_mut()) };
+    /// Windows whose state is borrowed by a call on this thread, with
+    /// whether `destroy` was requested meanwhile.
+    static DISPATCHING: RefCell<Vec<(usize, bool)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Defers destroying `hwnd` if its state is borrowed on this thread, until
+/// the outermost call holding the borrow returns. Returns whether it did.
+pub(crate) fn defer_destroy(hwnd: HWND) -> bool {
+    DISPATCHING.with(|dispatching| {
+        let mut dispatching = dispatching.borrow_mut();
+        match dispatching.iter_mut().find(|(h, _)| *h == hwnd as usize) {
+            Some(entry) => {
+                entry.1 = true;
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+/// Runs `f` with `handoff` registered as this thread's pending handoff, so
+/// that `WM_NCCREATE` only takes `lpCreateParams` for a handoff when it is
+/// this very pointer and not a parameter some other code passed.
+pub(crate) fn with_pending_handoff<R>(
+    handoff: &mut WindowStateHandoff,
+    f: impl FnOnce(LPVOID) -> R,
+) -> R {
+    let ptr = handoff as *mut WindowStateHandoff;
+    let previous = PENDING_HANDOFF.with(|pending| pending.replace(ptr));
+    let r = f(ptr as LPVOID);
+    PENDING_HANDOFF.with(|pending| pending.set(previous));
+    r
+}
+
+struct WindowStateSlot<S> {
+    value: RefCell<S>,
+    detached: Cell<bool>,
+}
+
+unsafe fn claim_handoff<S: 'static>(hwnd: HWND, lparam: LPARAM) -> bool {
+    use winapi::um::winuser::{SetWindowLongPtrW, CREATESTRUCTW, GWLP_USERDATA};
+    let create_struct = lparam as *const CREATESTRUCTW;
+    let handoff = (*create_struct).lpCreateParams as *mut WindowStateHandoff;
+    if handoff.is_null() || handoff != PENDING_HANDOFF.with(Cell::get) {
+        return true;
+    }
+    let state = match (*handoff).state.take() {
+        Some(state) => state,
+        None => return true,
+    };
+    match state.downcast::<S>() {
+        Ok(state) => {
+            let slot = Box::new(WindowStateSlot {
+                value: RefCell::new(*state),
+                detached: Cell::new(false),
+            });
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, Box::into_raw(slot) as _);
+            true
+        }
+        Err(_) => {
+            warn!(target: "apiw", "Window state type mismatch, expected: {}",
+                  std::any::type_name::<S>());
+            false
+        }
+    }
+}
+
+unsafe fn detach_slot(hwnd: HWND) {
+    use winapi::um::winuser::{SetWindowLongPtrW, GWLP_USERDATA};
+    SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0);
+}
+
+/// Window procedure body generated by `window_proc!(state: S, ...)`.
+///
+/// Messages that arrive before `WM_NCCREATE` or for windows created without
+/// state are not routed and fall back to `DefWindowProcW`. Messages that
+/// arrive while the state is borrowed are not routed either.
+#[doc(hidden)]
+pub unsafe fn translate_with_state<S: 'static, F>(
+    hwnd: HWND,
+    msg: UINT,
+    wparam: WPARAM,
+    lparam: LPARAM,
+    nest_proc: F,
+) -> LRESULT
+where
+    F: for<'a> FnOnce(WindowProcRequest<'a>, &mut S),
+{
+    translate_with_state_reentrant::<S, _, _>(hwnd, msg, wparam, lparam, nest_proc, |request| {
+        warn!(target: "apiw", "Window state already borrowed, message not routed: {:#x}",
+              request.args.msg);
+    })
+}
+
+/// Like `translate_with_state`, passing messages that arrive while the
+/// state is borrowed to `reentrant_proc` instead of dropping them.
+#[doc(hidden)]
+pub unsafe fn translate_with_state_reentrant<S: 'static, F, R>(
+    hwnd: HWND,
+    msg: UINT,
+    wparam: WPARAM,
+    lparam: LPARAM,
+    nest_proc: F,
+    reentrant_proc: R,
+) -> LRESULT
+where
+    F: for<'a> FnOnce(WindowProcRequest<'a>, &mut S),
+    R: for<'a> FnOnce(WindowProcRequest<'a>),
+{
+    use winapi::um::winuser::{DefWindowProcW, GetWindowLongPtrW, GWLP_USERDATA};
+    use winapi::um::winuser::{WM_NCCREATE, WM_NCDESTROY};
+
+    if msg == WM_NCCREATE && !claim_handoff::<S>(hwnd, lparam) {
+        return 0;
+    }
+
+    let slot_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut WindowStateSlot<S>;
+    if slot_ptr.is_null() {
+        if msg == WM_NCDESTROY {
+            notify_destroyed(hwnd);
+        }
+        return DefWindowProcW(hwnd, msg, wparam, lparam);
+    }
+
+    let mut response = WindowProcResponse::Fallback;
+    let mut destroy_deferred = false;
+    {
+        let slot = &*slot_ptr;
+        match slot.value.try_borrow_mut() {
+            Ok(mut state) => {
+                let request = WindowProcRequest {
+                    hwnd,
+                    args: WindowProcRequestArgs {
+                        msg,
+                        wparam,
+                        lparam,
+                    },
+                    response: Some(&mut response),
+                };
+                DISPATCHING.with(|d| d.borrow_mut().push((hwnd as usize, false)));
+                (nest_proc)(request, &mut state);
+                destroy_deferred = DISPATCHING
+                    .with(|d| d.borrow_mut().pop())
+                    .is_some_and(|(_, deferred)| deferred);
+            }
+            Err(_) => {
+                let request = WindowProcRequest {
+                    hwnd,
+                    args: WindowProcRequestArgs {
+                        msg,
+                        wparam,
+                        lparam,
+                    },
+                    response: Some(&mut response),
+                };
+                (reentrant_proc)(request);
+            }
+        }
+    }
+
+    if msg == WM_NCDESTROY {
+        notify_destroyed(hwnd);
+        detach_slot(hwnd);
+        (*slot_ptr).detached.set(true);
+    }
+    // Only the outermost call holding the borrow may free the slot.
+    if (*slot_ptr).detached.get() && (*slot_ptr).value.try_borrow_mut().is_ok() {
+        drop(Box::from_raw(slot_ptr));
+    }
+
+    let r = match response {
+        WindowProcResponse::Done(r) => r,
+        WindowProcResponse::Fallback => DefWindowProcW(hwnd, msg, wparam, lparam),
+    };
+    // The slot may be freed by the messages this sends, so it is not
+    // touched anymore.
+    if destroy_deferred {
+        use winapi::um::winuser::{DestroyWindow, IsWindow};
+        if IsWindow(hwnd) != 0 {
+            DestroyWindow(hwnd);
+        }
+    }
+    r
+}
@@ -0,0 +1,292 @@
+macro_rules! window_messages {
+    ($($name:ident = $value:expr,)*) => {
+        $(
+            pub const $name: u32 = $value;
+
+            #[cfg(windows)]
+            const _: () = assert!($name == winapi::um::winuser::$name);
+        )*
+
+        /// The symbolic name of a system-defined message.
+        pub fn message_name(msg: u32) -> Option<&'static str> {
+            match msg {
+                $($value => Some(stringify!($name)),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+window_messages! {
+    WM_NULL = 0x0000,
+    WM_CREATE = 0x0001,
+    WM_DESTROY = 0x0002,
+    WM_MOVE = 0x0003,
+    WM_SIZE = 0x0005,
+    WM_ACTIVATE = 0x0006,
+    WM_SETFOCUS = 0x0007,
+    WM_KILLFOCUS = 0x0008,
+    WM_ENABLE = 0x000A,
+    WM_SETREDRAW = 0x000B,
+    WM_SETTEXT = 0x000C,
+    WM_GETTEXT = 0x000D,
+    WM_GETTEXTLENGTH = 0x000E,
+    WM_PAINT = 0x000F,
+    WM_CLOSE = 0x0010,
+    WM_QUERYENDSESSION = 0x0011,
+    WM_QUIT = 0x0012,
+    WM_QUERYOPEN = 0x0013,
+    WM_ERASEBKGND = 0x0014,
+    WM_SYSCOLORCHANGE = 0x0015,
+    WM_ENDSESSION = 0x0016,
+    WM_SHOWWINDOW = 0x0018,
+    WM_SETTINGCHANGE = 0x001A,
+    WM_DEVMODECHANGE = 0x001B,
+    WM_ACTIVATEAPP = 0x001C,
+    WM_FONTCHANGE = 0x001D,
+    WM_TIMECHANGE = 0x001E,
+    WM_CANCELMODE = 0x001F,
+    WM_SETCURSOR = 0x0020,
+    WM_MOUSEACTIVATE = 0x0021,
+    WM_CHILDACTIVATE = 0x0022,
+    WM_QUEUESYNC = 0x0023,
+    WM_GETMINMAXINFO = 0x0024,
+    WM_PAINTICON = 0x0026,
+    WM_ICONERASEBKGND = 0x0027,
+    WM_NEXTDLGCTL = 0x0028,
+    WM_SPOOLERSTATUS = 0x002A,
+    WM_DRAWITEM = 0x002B,
+    WM_MEASUREITEM = 0x002C,
+    WM_DELETEITEM = 0x002D,
+    WM_VKEYTOITEM = 0x002E,
+    WM_CHARTOITEM = 0x002F,
+    WM_SETFONT = 0x0030,
+    WM_GETFONT = 0x0031,
+    WM_SETHOTKEY = 0x0032,
+    WM_GETHOTKEY = 0x0033,
+    WM_QUERYDRAGICON = 0x0037,
+    WM_COMPAREITEM = 0x0039,
+    WM_GETOBJECT = 0x003D,
+    WM_COMPACTING = 0x0041,
+    WM_WINDOWPOSCHANGING = 0x0046,
+    WM_WINDOWPOSCHANGED = 0x0047,
+    WM_COPYDATA = 0x004A,
+    WM_CANCELJOURNAL = 0x004B,
+    WM_NOTIFY = 0x004E,
+    WM_INPUTLANGCHANGEREQUEST = 0x0050,
+    WM_INPUTLANGCHANGE = 0x0051,
+    WM_TCARD = 0x0052,
+    WM_HELP = 0x0053,
+    WM_USERCHANGED = 0x0054,
+    WM_NOTIFYFORMAT = 0x0055,
+    WM_CONTEXTMENU = 0x007B,
+    WM_STYLECHANGING = 0x007C,
+    WM_STYLECHANGED = 0x007D,
+    WM_DISPLAYCHANGE = 0x007E,
+    WM_GETICON = 0x007F,
+    WM_SETICON = 0x0080,
+    WM_NCCREATE = 0x0081,
+    WM_NCDESTROY = 0x0082,
+    WM_NCCALCSIZE = 0x0083,
+    WM_NCHITTEST = 0x0084,
+    WM_NCPAINT = 0x0085,
+    WM_NCACTIVATE = 0x0086,
+    WM_GETDLGCODE = 0x0087,
+    WM_SYNCPAINT = 0x0088,
+    WM_NCMOUSEMOVE = 0x00A0,
+    WM_NCLBUTTONDOWN = 0x00A1,
+    WM_NCLBUTTONUP = 0x00A2,
+    WM_NCLBUTTONDBLCLK = 0x00A3,
+    WM_NCRBUTTONDOWN = 0x00A4,
+    WM_NCRBUTTONUP = 0x00A5,
+    WM_NCRBUTTONDBLCLK = 0x00A6,
+    WM_NCMBUTTONDOWN = 0x00A7,
+    WM_NCMBUTTONUP = 0x00A8,
+    WM_NCMBUTTONDBLCLK = 0x00A9,
+    WM_NCXBUTTONDOWN = 0x00AB,
+    WM_NCXBUTTONUP = 0x00AC,
+    WM_NCXBUTTONDBLCLK = 0x00AD,
+    WM_INPUT_DEVICE_CHANGE = 0x00FE,
+    WM_INPUT = 0x00FF,
+    WM_KEYDOWN = 0x0100,
+    WM_KEYUP = 0x0101,
+    WM_CHAR = 0x0102,
+    WM_DEADCHAR = 0x0103,
+    WM_SYSKEYDOWN = 0x0104,
+    WM_SYSKEYUP = 0x0105,
+    WM_SYSCHAR = 0x0106,
+    WM_SYSDEADCHAR = 0x0107,
+    WM_UNICHAR = 0x0109,
+    WM_IME_STARTCOMPOSITION = 0x010D,
+    WM_IME_ENDCOMPOSITION = 0x010E,
+    WM_IME_COMPOSITION = 0x010F,
+    WM_INITDIALOG = 0x0110,
+    WM_COMMAND = 0x0111,
+    WM_SYSCOMMAND = 0x0112,
+    WM_TIMER = 0x0113,
+    WM_HSCROLL = 0x0114,
+    WM_VSCROLL = 0x0115,
+    WM_INITMENU = 0x0116,
+    WM_INITMENUPOPUP = 0x0117,
+    WM_GESTURE = 0x0119,
+    WM_GESTURENOTIFY = 0x011A,
+    WM_MENUSELECT = 0x011F,
+    WM_MENUCHAR = 0x0120,
+    WM_ENTERIDLE = 0x0121,
+    WM_MENURBUTTONUP = 0x0122,
+    WM_MENUDRAG = 0x0123,
+    WM_MENUGETOBJECT = 0x0124,
+    WM_UNINITMENUPOPUP = 0x0125,
+    WM_MENUCOMMAND = 0x0126,
+    WM_CHANGEUISTATE = 0x0127,
+    WM_UPDATEUISTATE = 0x0128,
+    WM_QUERYUISTATE = 0x0129,
+    WM_CTLCOLORMSGBOX = 0x0132,
+    WM_CTLCOLOREDIT = 0x0133,
+    WM_CTLCOLORLISTBOX = 0x0134,
+    WM_CTLCOLORBTN = 0x0135,
+    WM_CTLCOLORDLG = 0x0136,
+    WM_CTLCOLORSCROLLBAR = 0x0137,
+    WM_CTLCOLORSTATIC = 0x0138,
+    WM_MOUSEMOVE = 0x0200,
+    WM_LBUTTONDOWN = 0x0201,
+    WM_LBUTTONUP = 0x0202,
+    WM_LBUTTONDBLCLK = 0x0203,
+    WM_RBUTTONDOWN = 0x0204,
+    WM_RBUTTONUP = 0x0205,
+    WM_RBUTTONDBLCLK = 0x0206,
+    WM_MBUTTONDOWN = 0x0207,
+    WM_MBUTTONUP = 0x0208,
+    WM_MBUTTONDBLCLK = 0x0209,
+    WM_MOUSEWHEEL = 0x020A,
+    WM_XBUTTONDOWN = 0x020B,
+    WM_XBUTTONUP = 0x020C,
+    WM_XBUTTONDBLCLK = 0x020D,
+    WM_MOUSEHWHEEL = 0x020E,
+    WM_PARENTNOTIFY = 0x0210,
+    WM_ENTERMENULOOP = 0x0211,
+    WM_EXITMENULOOP = 0x0212,
+    WM_NEXTMENU = 0x0213,
+    WM_SIZING = 0x0214,
+    WM_CAPTURECHANGED = 0x0215,
+    WM_MOVING = 0x0216,
+    WM_POWERBROADCAST = 0x0218,
+    WM_DEVICECHANGE = 0x0219,
+    WM_ENTERSIZEMOVE = 0x0231,
+    WM_EXITSIZEMOVE = 0x0232,
+    WM_DROPFILES = 0x0233,
+    WM_IME_SETCONTEXT = 0x0281,
+    WM_IME_NOTIFY = 0x0282,
+    WM_IME_CHAR = 0x0286,
+    WM_NCMOUSEHOVER = 0x02A0,
+    WM_MOUSEHOVER = 0x02A1,
+    WM_NCMOUSELEAVE = 0x02A2,
+    WM_MOUSELEAVE = 0x02A3,
+    WM_DPICHANGED = 0x02E0,
+    WM_CUT = 0x0300,
+    WM_COPY = 0x0301,
+    WM_PASTE = 0x0302,
+    WM_CLEAR = 0x0303,
+    WM_UNDO = 0x0304,
+    WM_RENDERFORMAT = 0x0305,
+    WM_RENDERALLFORMATS = 0x0306,
+    WM_DESTROYCLIPBOARD = 0x0307,
+    WM_DRAWCLIPBOARD = 0x0308,
+    WM_PAINTCLIPBOARD = 0x0309,
+    WM_QUERYNEWPALETTE = 0x030F,
+    WM_PALETTECHANGED = 0x0311,
+    WM_HOTKEY = 0x0312,
+    WM_PRINT = 0x0317,
+    WM_PRINTCLIENT = 0x0318,
+    WM_APPCOMMAND = 0x0319,
+    WM_THEMECHANGED = 0x031A,
+    WM_CLIPBOARDUPDATE = 0x031D,
+    WM_DWMCOMPOSITIONCHANGED = 0x031E,
+    WM_USER = 0x0400,
+    WM_APP = 0x8000,
+}
+
+pub const WM_KEYFIRST: u32 = WM_KEYDOWN;
+pub const WM_KEYLAST: u32 = WM_UNICHAR;
+pub const WM_MOUSEFIRST: u32 = WM_MOUSEMOVE;
+pub const WM_MOUSELAST: u32 = WM_MOUSEHWHEEL;
+
+/// An inclusive range of message identifiers, as taken by GetMessage and
+/// PeekMessage. `ALL` (zero to zero) matches every message.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct MessageRange {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl MessageRange {
+    pub const ALL: MessageRange = MessageRange::new(0, 0);
+    pub const KEYBOARD: MessageRange = MessageRange::new(WM_KEYFIRST, WM_KEYLAST);
+    pub const MOUSE: MessageRange = MessageRange::new(WM_MOUSEFIRST, WM_MOUSELAST);
+    pub const PAINT: MessageRange = MessageRange::single(WM_PAINT);
+    pub const TIMER: MessageRange = MessageRange::single(WM_TIMER);
+    pub const USER: MessageRange = MessageRange::new(WM_USER, WM_APP - 1);
+    pub const APP: MessageRange = MessageRange::new(WM_APP, 0xBFFF);
+
+    pub const fn new(min: u32, max: u32) -> Self {
+        MessageRange { min, max }
+    }
+
+    pub const fn single(msg: u32) -> Self {
+        MessageRange { min: msg, max: msg }
+    }
+
+    pub fn contains(&self, msg: u32) -> bool {
+        (self.min == 0 && self.max == 0) || (self.min <= msg && msg <= self.max)
+    }
+}
+
+/// Formats a message identifier the way a message spy shows it: the
+/// WM_* name when known, otherwise its place in the private ranges.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct MessageName(pub u32);
+
+impl std::fmt::Display for MessageName {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let msg = self.0;
+        if let Some(name) = message_name(msg) {
+            f.write_str(name)
+        } else if MessageRange::USER.contains(msg) {
+            write!(f, "WM_USER+{}", msg - WM_USER)
+        } else if MessageRange::APP.contains(msg) {
+            write!(f, "WM_APP+{}", msg - WM_APP)
+        } else if (0xC000..=0xFFFF).contains(&msg) {
+            write!(f, "registered {:#06x}", msg)
+        } else {
+            write!(f, "{:#06x}", msg)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_range_presets() {
+        assert!(MessageRange::ALL.contains(WM_QUIT));
+        assert!(MessageRange::KEYBOARD.contains(WM_CHAR));
+        assert!(!MessageRange::KEYBOARD.contains(WM_MOUSEMOVE));
+        assert!(MessageRange::MOUSE.contains(WM_MOUSEHWHEEL));
+        assert!(!MessageRange::MOUSE.contains(WM_MOUSELEAVE));
+        assert!(MessageRange::APP.contains(WM_APP + 3));
+        assert!(!MessageRange::PAINT.contains(WM_TIMER));
+    }
+
+    #[test]
+    fn names_messages() {
+        assert_eq!(message_name(WM_PAINT), Some("WM_PAINT"));
+        assert_eq!(message_name(0x0004), None);
+        assert_eq!(MessageName(WM_LBUTTONDOWN).to_string(), "WM_LBUTTONDOWN");
+        assert_eq!(MessageName(WM_USER).to_string(), "WM_USER");
+        assert_eq!(MessageName(WM_USER + 5).to_string(), "WM_USER+5");
+        assert_eq!(MessageName(WM_APP + 1).to_string(), "WM_APP+1");
+        assert_eq!(MessageName(0xC123).to_string(), "registered 0xc123");
+        assert_eq!(MessageName(0x0004).to_string(), "0x0004");
+    }
+}